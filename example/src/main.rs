@@ -90,12 +90,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "http://localhost:11434".to_string(),
                         model_name.to_string(),
                     );
-                    let messages = vec![Message {
-                        role: "user".to_string(),
-                        content: prompt.unwrap_or_else(|| "What is in this image?".to_string()),
-                        images: None,
-                        tool_calls: None,
-                    }];
+                    let messages = vec![Message::user(
+                        prompt.unwrap_or_else(|| "What is in this image?".to_string()),
+                    )];
 
                     let (response, _) = client
                         .send_chat_request_with_images(&messages, vec![image_path.to_string()])
@@ -159,12 +156,7 @@ async fn chat_stream(mut client: OllamaClient) -> Result<(), Box<dyn std::error:
             break;
         }
 
-        messages.push(Message {
-            role: "user".to_string(),
-            content: user_input.to_string(),
-            images: None,
-            tool_calls: None,
-        });
+        messages.push(Message::user(user_input));
 
         print!("{}: ", client.model);
         io::stdout().flush()?;
@@ -190,10 +182,8 @@ async fn chat_stream(mut client: OllamaClient) -> Result<(), Box<dyn std::error:
         }
 
         messages.push(Message {
-            role: "assistant".to_string(),
-            content: full_response,
-            images: None,
             tool_calls: tool_calls.clone(),
+            ..Message::assistant(full_response)
         });
 
         if let Some(tool_calls) = tool_calls {
@@ -220,12 +210,7 @@ async fn chat_stream(mut client: OllamaClient) -> Result<(), Box<dyn std::error:
                 }
             }
 
-            messages.push(Message {
-                role: "assistant".to_string(),
-                content: tool_response,
-                images: None,
-                tool_calls: None,
-            });
+            messages.push(Message::assistant(tool_response));
         }
     }
 
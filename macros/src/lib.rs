@@ -3,15 +3,22 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use serde_json::json;
-use syn::{ItemFn, Pat, PatType, Type, parse_macro_input};
+use syn::ext::IdentExt;
+use syn::meta::ParseNestedMeta;
+use syn::{
+    Attribute, Data, DeriveInput, Fields, GenericArgument, ItemFn, LitStr, Pat, PatType,
+    PathArguments, ReturnType, Token, Type, parse_macro_input,
+};
 
 fn rust_type_to_json_type(ty: &Type) -> &'static str {
     if let Type::Path(type_path) = ty {
         if type_path.path.is_ident("String") {
             return "string";
-        } else if type_path.path.is_ident("i32")
-            || type_path.path.is_ident("i64")
-            || type_path.path.is_ident("usize")
+        } else if [
+            "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32", "u64", "usize",
+        ]
+        .iter()
+        .any(|int| type_path.path.is_ident(int))
         {
             return "integer";
         } else if type_path.path.is_ident("f32") || type_path.path.is_ident("f64") {
@@ -23,6 +30,146 @@ fn rust_type_to_json_type(ty: &Type) -> &'static str {
     "string"
 }
 
+fn returns_result(output: &ReturnType) -> bool {
+    if let ReturnType::Type(_, ty) = output
+        && let Type::Path(type_path) = &**ty
+        && let Some(segment) = type_path.path.segments.last()
+    {
+        return segment.ident == "Result";
+    }
+    false
+}
+
+fn generic_argument<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    if let Type::Path(type_path) = ty
+        && let Some(segment) = type_path.path.segments.last()
+        && segment.ident == wrapper
+        && let PathArguments::AngleBracketed(args) = &segment.arguments
+        && let Some(GenericArgument::Type(inner)) = args.args.first()
+    {
+        return Some(inner);
+    }
+    None
+}
+
+fn type_schema(ty: &Type) -> serde_json::Value {
+    if let Some(inner) = generic_argument(ty, "Option") {
+        return type_schema(inner);
+    }
+    if let Some(inner) = generic_argument(ty, "Vec") {
+        return json!({ "type": "array", "items": type_schema(inner) });
+    }
+    json!({ "type": rust_type_to_json_type(ty) })
+}
+
+fn doc_comment(attrs: &[Attribute]) -> String {
+    let mut description = String::new();
+    for attr in attrs {
+        if attr.path().is_ident("doc")
+            && let Ok(name_value) = attr.meta.require_name_value()
+            && let syn::Expr::Lit(expr_lit) = &name_value.value
+            && let syn::Lit::Str(lit_str) = &expr_lit.lit
+        {
+            description.push_str(lit_str.value().trim());
+            description.push(' ');
+        }
+    }
+    description.trim().to_string()
+}
+
+// The `#[serde(...)]` settings that change which arguments a struct
+// deserializes from, so the schema can name the same ones.
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<LitStr>,
+    skip: bool,
+    default: bool,
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut serde = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                if let Some(name) = deserialize_name(&meta)? {
+                    serde.rename = Some(name.value());
+                }
+            } else if meta.path.is_ident("rename_all") {
+                if let Some(rule) = deserialize_name(&meta)? {
+                    serde.rename_all = Some(rule);
+                }
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                serde.skip = true;
+            } else {
+                if meta.path.is_ident("default") {
+                    serde.default = true;
+                }
+                skip_meta(&meta)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(serde)
+}
+
+// Reads `rename = "..."` or the `deserialize` half of
+// `rename(serialize = "...", deserialize = "...")`.
+fn deserialize_name(meta: &ParseNestedMeta) -> syn::Result<Option<LitStr>> {
+    if meta.input.peek(Token![=]) {
+        return Ok(Some(meta.value()?.parse()?));
+    }
+    let mut name = None;
+    meta.parse_nested_meta(|nested| {
+        if nested.path.is_ident("deserialize") {
+            name = Some(nested.value()?.parse()?);
+        } else {
+            skip_meta(&nested)?;
+        }
+        Ok(())
+    })?;
+    Ok(name)
+}
+
+fn skip_meta(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|nested| skip_meta(&nested))?;
+    }
+    Ok(())
+}
+
+// Applies a `rename_all` rule to a snake_case field name, the way serde does.
+fn rename_field(field: &str, rule: &str) -> Option<String> {
+    let pascal = || {
+        field
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            })
+            .collect::<String>()
+    };
+    Some(match rule {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_ascii_lowercase().to_string() + chars.as_str()
+            })
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.replace('_', "-").to_ascii_uppercase(),
+        _ => return None,
+    })
+}
+
 #[proc_macro_attribute]
 pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let func = parse_macro_input!(item as ItemFn);
@@ -30,20 +177,7 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let func_name_str = func_name.to_string();
     let tool_func_name = format_ident!("{}_tool", func_name);
 
-    let mut description = String::new();
-    for attr in &func.attrs {
-        if attr.path().is_ident("doc") {
-            if let Ok(name_value) = attr.meta.require_name_value() {
-                if let syn::Expr::Lit(expr_lit) = &name_value.value {
-                    if let syn::Lit::Str(lit_str) = &expr_lit.lit {
-                        description.push_str(&lit_str.value().trim());
-                        description.push(' ');
-                    }
-                }
-            }
-        }
-    }
-    let description = description.trim().to_string();
+    let description = doc_comment(&func.attrs);
 
     let mut params_properties = serde_json::Map::new();
     let mut required_params = Vec::new();
@@ -52,23 +186,23 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut arg_types = Vec::new();
 
     for input in &func.sig.inputs {
-        if let syn::FnArg::Typed(PatType { pat, ty, .. }) = input {
-            if let Pat::Ident(pat_ident) = &**pat {
-                let arg_name = pat_ident.ident.to_string();
-                let json_type = rust_type_to_json_type(ty);
-
-                params_properties.insert(
-                    arg_name.clone(),
-                    json!({
-                        "type": json_type,
-                        "description": ""
-                    }),
-                );
-                required_params.push(arg_name.clone());
-                arg_names.push(pat_ident.ident.clone());
-                arg_names_str.push(arg_name.clone());
-                arg_types.push(ty.clone());
-            }
+        if let syn::FnArg::Typed(PatType { pat, ty, .. }) = input
+            && let Pat::Ident(pat_ident) = &**pat
+        {
+            let arg_name = pat_ident.ident.to_string();
+            let json_type = rust_type_to_json_type(ty);
+
+            params_properties.insert(
+                arg_name.clone(),
+                json!({
+                    "type": json_type,
+                    "description": ""
+                }),
+            );
+            required_params.push(arg_name.clone());
+            arg_names.push(pat_ident.ident.clone());
+            arg_names_str.push(arg_name.clone());
+            arg_types.push(ty.clone());
         }
     }
 
@@ -79,6 +213,43 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     })
     .to_string();
 
+    let is_async = func.sig.asyncness.is_some();
+    let invoke = if is_async {
+        quote! { #func_name(#(#arg_names),*).await }
+    } else {
+        quote! { #func_name(#(#arg_names),*) }
+    };
+    let call = if returns_result(&func.sig.output) {
+        quote! {
+            #invoke
+                .map(|value| value.to_string())
+                .map_err(|e| e.to_string())
+        }
+    } else {
+        quote! { Ok(#invoke.to_string()) }
+    };
+    let read_args = quote! {
+        #(let #arg_names: #arg_types = serde_json::from_value(args[#arg_names_str].clone())
+            .map_err(|e| format!("Invalid argument `{}`: {}", #arg_names_str, e))?;)*
+    };
+    let function = if is_async {
+        quote! {
+            ollama_rust::ToolFunction::Async(std::sync::Arc::new(|args| {
+                Box::pin(async move {
+                    #read_args
+                    #call
+                })
+            }))
+        }
+    } else {
+        quote! {
+            ollama_rust::ToolFunction::Sync(std::sync::Arc::new(|args| {
+                #read_args
+                #call
+            }))
+        }
+    };
+
     let expanded = quote! {
         pub fn #tool_func_name() -> ollama_rust::Tool {
             #func
@@ -87,13 +258,148 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 name: #func_name_str.to_string(),
                 description: #description.to_string(),
                 parameters: serde_json::from_str(#parameters_json).unwrap(),
-                function: Box::new(|args| {
-                    #(let #arg_names: #arg_types = serde_json::from_value(args[#arg_names_str].clone()).unwrap();)*
-                    #func_name(#(#arg_names),*).to_string()
-                }),
+                function: #function,
             }
         }
     };
 
     TokenStream::from(expanded)
 }
+
+#[proc_macro_derive(ToolParameters)]
+pub fn derive_tool_parameters(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return syn::Error::new_spanned(
+                    name,
+                    "ToolParameters can only be derived for structs with named fields",
+                )
+                .to_compile_error()
+                .into();
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "ToolParameters can only be derived for structs")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let container = match serde_attrs(&input.attrs) {
+        Ok(container) => container,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Some(rule) = &container.rename_all
+        && rename_field("", &rule.value()).is_none()
+    {
+        return syn::Error::new_spanned(rule, "unknown serde rename_all rule")
+            .to_compile_error()
+            .into();
+    }
+
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let attrs = match serde_attrs(&field.attrs) {
+            Ok(attrs) => attrs,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        if attrs.skip {
+            continue;
+        }
+        // The schema has to name the keys serde will actually read.
+        let field_name = match (attrs.rename, &container.rename_all) {
+            (Some(rename), _) => rename,
+            (None, Some(rule)) => {
+                rename_field(&ident.unraw().to_string(), &rule.value()).unwrap_or_default()
+            }
+            (None, None) => ident.unraw().to_string(),
+        };
+
+        let mut schema = type_schema(&field.ty);
+        schema["description"] = json!(doc_comment(&field.attrs));
+        properties.insert(field_name.clone(), schema);
+
+        if generic_argument(&field.ty, "Option").is_none() && !attrs.default && !container.default {
+            required.push(field_name);
+        }
+    }
+
+    let parameters_json = json!({
+        "type": "object",
+        "properties": properties,
+        "required": required
+    })
+    .to_string();
+
+    let expanded = quote! {
+        impl ollama_rust::ToolParameters for #name {
+            fn parameters() -> serde_json::Value {
+                serde_json::from_str(#parameters_json).unwrap()
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_all_rules() {
+        assert_eq!(
+            rename_field("max_results", "camelCase").unwrap(),
+            "maxResults"
+        );
+        assert_eq!(
+            rename_field("max_results", "PascalCase").unwrap(),
+            "MaxResults"
+        );
+        assert_eq!(
+            rename_field("max_results", "kebab-case").unwrap(),
+            "max-results"
+        );
+        assert_eq!(
+            rename_field("max_results", "SCREAMING_SNAKE_CASE").unwrap(),
+            "MAX_RESULTS"
+        );
+        assert!(rename_field("max_results", "Title Case").is_none());
+    }
+
+    #[test]
+    fn reads_serde_attributes() {
+        let field: syn::Field = syn::parse_quote! {
+            #[serde(default, rename(serialize = "out", deserialize = "in"), with = "module")]
+            value: String
+        };
+        let attrs = serde_attrs(&field.attrs).unwrap();
+        assert_eq!(attrs.rename.as_deref(), Some("in"));
+        assert!(attrs.default);
+        assert!(!attrs.skip);
+
+        let input: DeriveInput = syn::parse_quote! {
+            #[serde(rename_all = "camelCase")]
+            struct Args {
+                #[serde(skip_deserializing)]
+                cache: String,
+            }
+        };
+        let container = serde_attrs(&input.attrs).unwrap();
+        assert_eq!(container.rename_all.unwrap().value(), "camelCase");
+        let Data::Struct(data) = &input.data else {
+            unreachable!()
+        };
+        let cache = data.fields.iter().next().unwrap();
+        assert!(serde_attrs(&cache.attrs).unwrap().skip);
+    }
+}
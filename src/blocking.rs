@@ -0,0 +1,404 @@
+//! A synchronous wrapper around [`OllamaClient`] for code that doesn't run
+//! inside an async runtime. Each call drives the async client on an internal
+//! single-threaded Tokio runtime, so these methods must not be called from
+//! within another runtime.
+//!
+//! The cancellable streams have no counterpart here: dropping a
+//! [`BlockingStream`] closes the connection the same way. Tool calls can be
+//! handled through [`BlockingOllamaClient::client`].
+
+use crate::{
+    ChatResponse, ChatStreamItem, ChatTrace, CreateModelRequest, EmbedResponse, EmbeddingsResponse,
+    GenerateRequest, GenerateResponse, Message, Model, ModelInfo, OllamaClient, OllamaError,
+    Options, PullProgress, ResponseStream, RunningModel, ToolCall, ToolParameters, Version,
+};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use tokio::runtime::{Builder, Runtime};
+
+pub struct BlockingOllamaClient {
+    client: OllamaClient,
+    runtime: Runtime,
+}
+
+/// A response stream as a plain iterator. Each call to `next` blocks the
+/// calling thread until the next item arrives.
+pub struct BlockingStream<'a, T> {
+    stream: ResponseStream<T>,
+    runtime: &'a Runtime,
+}
+
+impl<T> Iterator for BlockingStream<'_, T> {
+    type Item = Result<T, OllamaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+impl BlockingOllamaClient {
+    pub fn new(endpoint: String, model: String) -> Result<Self, OllamaError> {
+        Self::from_client(OllamaClient::new(endpoint, model))
+    }
+
+    pub fn from_client(client: OllamaClient) -> Result<Self, OllamaError> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { client, runtime })
+    }
+
+    pub fn client(&self) -> &OllamaClient {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut OllamaClient {
+        &mut self.client
+    }
+
+    pub fn health(&self) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.health())
+    }
+
+    pub fn ping(&self) -> bool {
+        self.runtime.block_on(self.client.ping())
+    }
+
+    pub fn version(&self) -> Result<Version, OllamaError> {
+        self.runtime.block_on(self.client.version())
+    }
+
+    pub fn list_local_models(&self) -> Result<Vec<Model>, OllamaError> {
+        self.runtime.block_on(self.client.list_local_models())
+    }
+
+    pub fn list_model_names(&self) -> Result<Vec<String>, OllamaError> {
+        self.runtime.block_on(self.client.list_model_names())
+    }
+
+    pub fn has_model(&self, name: &str) -> Result<bool, OllamaError> {
+        self.runtime.block_on(self.client.has_model(name))
+    }
+
+    pub fn list_running_models(&self) -> Result<Vec<RunningModel>, OllamaError> {
+        self.runtime.block_on(self.client.list_running_models())
+    }
+
+    pub fn is_loaded(&self, name: &str) -> Result<bool, OllamaError> {
+        self.runtime.block_on(self.client.is_loaded(name))
+    }
+
+    pub fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, OllamaError> {
+        self.runtime
+            .block_on(self.client.show_model_info(model_name))
+    }
+
+    pub fn embeddings(
+        &self,
+        prompt: &str,
+        model: Option<&str>,
+    ) -> Result<EmbeddingsResponse, OllamaError> {
+        self.runtime.block_on(self.client.embeddings(prompt, model))
+    }
+
+    pub fn embed_batch(
+        &self,
+        inputs: &[String],
+        model: Option<&str>,
+    ) -> Result<EmbedResponse, OllamaError> {
+        self.runtime
+            .block_on(self.client.embed_batch(inputs, model))
+    }
+
+    pub fn embed_files(
+        &self,
+        paths: &[PathBuf],
+        chunk_size: usize,
+        overlap: usize,
+    ) -> Result<Vec<(PathBuf, String, Vec<f32>)>, OllamaError> {
+        self.runtime
+            .block_on(self.client.embed_files(paths, chunk_size, overlap))
+    }
+
+    pub fn rank_by_similarity(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<(String, f32)>, OllamaError> {
+        self.runtime
+            .block_on(self.client.rank_by_similarity(query, documents))
+    }
+
+    pub fn delete_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.delete_model(model_name))
+    }
+
+    pub fn copy_model(&self, source: &str, destination: &str) -> Result<(), OllamaError> {
+        self.runtime
+            .block_on(self.client.copy_model(source, destination))
+    }
+
+    pub fn blob_exists(&self, digest: &str) -> Result<bool, OllamaError> {
+        self.runtime.block_on(self.client.blob_exists(digest))
+    }
+
+    pub fn create_blob(&self, digest: &str, data: Vec<u8>) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.create_blob(digest, data))
+    }
+
+    pub fn load_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.load_model(model_name))
+    }
+
+    pub fn unload_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.unload_model(model_name))
+    }
+
+    pub fn stop_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.stop_model(model_name))
+    }
+
+    pub fn pull_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.pull_model(model_name))
+    }
+
+    pub fn pull_model_with_progress(
+        &self,
+        model_name: &str,
+        on_progress: impl FnMut(&PullProgress, Option<f64>),
+    ) -> Result<(), OllamaError> {
+        self.runtime.block_on(
+            self.client
+                .pull_model_with_progress(model_name, on_progress),
+        )
+    }
+
+    pub fn pull_model_stream(
+        &self,
+        model_name: &str,
+    ) -> Result<BlockingStream<'_, PullProgress>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.pull_model_stream(model_name))?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn push_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.runtime.block_on(self.client.push_model(model_name))
+    }
+
+    pub fn push_model_stream(
+        &self,
+        model_name: &str,
+    ) -> Result<BlockingStream<'_, PullProgress>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.push_model_stream(model_name))?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn create_model(&self, name: &str, modelfile: &str) -> Result<(), OllamaError> {
+        self.runtime
+            .block_on(self.client.create_model(name, modelfile))
+    }
+
+    pub fn create_model_stream(
+        &self,
+        name: &str,
+        modelfile: &str,
+    ) -> Result<BlockingStream<'_, PullProgress>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.create_model_stream(name, modelfile))?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn create_model_stream_with(
+        &self,
+        request: CreateModelRequest,
+    ) -> Result<BlockingStream<'_, PullProgress>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.create_model_stream_with(request))?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn send_chat_request(
+        &self,
+        messages: &[Message],
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request(messages))
+    }
+
+    pub fn send_chat_request_stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<BlockingStream<'_, ChatStreamItem>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.send_chat_request_stream(messages))?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn send_chat_request_with_model(
+        &self,
+        messages: &[Message],
+        model: &str,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request_with_model(messages, model))
+    }
+
+    pub fn send_chat_request_stream_with_model(
+        &self,
+        messages: &[Message],
+        model: &str,
+    ) -> Result<BlockingStream<'_, ChatStreamItem>, OllamaError> {
+        let stream = self.runtime.block_on(
+            self.client
+                .send_chat_request_stream_with_model(messages, model),
+        )?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn send_chat_request_with_options(
+        &self,
+        messages: &[Message],
+        options: &Options,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime.block_on(
+            self.client
+                .send_chat_request_with_options(messages, options),
+        )
+    }
+
+    pub fn send_chat_request_stream_with_options(
+        &self,
+        messages: &[Message],
+        options: &Options,
+    ) -> Result<BlockingStream<'_, ChatStreamItem>, OllamaError> {
+        let stream = self.runtime.block_on(
+            self.client
+                .send_chat_request_stream_with_options(messages, options),
+        )?;
+        Ok(self.iterate(stream))
+    }
+
+    pub fn send_chat_request_printing(
+        &self,
+        messages: &[Message],
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request_printing(messages))
+    }
+
+    pub fn send_chat_message(&self, messages: &[Message]) -> Result<Message, OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_message(messages))
+    }
+
+    pub fn send_chat_message_no_stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Message, OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_message_no_stream(messages))
+    }
+
+    pub fn send_chat_request_with_metrics(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request_with_metrics(messages))
+    }
+
+    pub fn chat_many(
+        &self,
+        batches: Vec<Vec<Message>>,
+        concurrency: usize,
+    ) -> Vec<Result<String, OllamaError>> {
+        self.runtime
+            .block_on(self.client.chat_many(batches, concurrency))
+    }
+
+    pub fn send_chat_request_with_images(
+        &self,
+        messages: &[Message],
+        image_paths: Vec<String>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime.block_on(
+            self.client
+                .send_chat_request_with_images(messages, image_paths),
+        )
+    }
+
+    pub fn send_chat_request_with_image_bytes(
+        &self,
+        messages: &[Message],
+        images: Vec<Vec<u8>>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        self.runtime.block_on(
+            self.client
+                .send_chat_request_with_image_bytes(messages, images),
+        )
+    }
+
+    pub fn send_chat_request_json<T: DeserializeOwned>(
+        &self,
+        messages: &[Message],
+        schema: Option<serde_json::Value>,
+    ) -> Result<T, OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request_json(messages, schema))
+    }
+
+    pub fn send_chat_request_typed<T: ToolParameters>(
+        &self,
+        messages: &[Message],
+    ) -> Result<T, OllamaError> {
+        self.runtime
+            .block_on(self.client.send_chat_request_typed(messages))
+    }
+
+    pub fn chat_with_tools(&self, messages: Vec<Message>) -> Result<Message, OllamaError> {
+        self.runtime.block_on(self.client.chat_with_tools(messages))
+    }
+
+    pub fn chat_with_trace(&self, messages: Vec<Message>) -> Result<ChatTrace, OllamaError> {
+        self.runtime.block_on(self.client.chat_with_trace(messages))
+    }
+
+    pub fn generate(
+        &self,
+        request: impl Into<GenerateRequest>,
+    ) -> Result<GenerateResponse, OllamaError> {
+        self.runtime.block_on(self.client.generate(request))
+    }
+
+    pub fn generate_with_callback(
+        &self,
+        request: impl Into<GenerateRequest>,
+        on_token: impl FnMut(&str),
+    ) -> Result<GenerateResponse, OllamaError> {
+        self.runtime
+            .block_on(self.client.generate_with_callback(request, on_token))
+    }
+
+    pub fn generate_stream(
+        &self,
+        request: impl Into<GenerateRequest>,
+    ) -> Result<BlockingStream<'_, GenerateResponse>, OllamaError> {
+        let stream = self
+            .runtime
+            .block_on(self.client.generate_stream(request))?;
+        Ok(self.iterate(stream))
+    }
+
+    fn iterate<T>(&self, stream: ResponseStream<T>) -> BlockingStream<'_, T> {
+        BlockingStream {
+            stream,
+            runtime: &self.runtime,
+        }
+    }
+}
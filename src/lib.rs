@@ -1,345 +1,3855 @@
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+mod template;
+
+pub use template::render_template;
+
+// Logging goes through `tracing` when the feature is enabled and is compiled
+// out otherwise, so the library never writes to stdout/stderr on its own.
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_event!(debug, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_event!(info, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_event!(warn, $($arg)*) };
+}
+
 use base64::{Engine as _, engine::general_purpose};
-use futures_util::{Stream, StreamExt};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use futures_util::{Stream, StreamExt, future::join_all};
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::json;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
 use std::io::Write;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+pub use tokio_util::sync::CancellationToken;
+
+#[derive(Debug)]
+pub enum OllamaError {
+    Http(reqwest::Error),
+    Status { code: u16, body: String },
+    Deserialize(serde_json::Error),
+    Io(std::io::Error),
+    Stream(String),
+    InvalidResponse(String),
+    ToolLoopLimit(usize),
+    DimensionMismatch { left: usize, right: usize },
+    InvalidInput(String),
+    ToolsNotSupported(String),
+}
+
+impl fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OllamaError::Http(e) => write!(f, "HTTP error: {}", e),
+            OllamaError::Status { code, body } => write!(f, "Server returned {}: {}", code, body),
+            OllamaError::Deserialize(e) => write!(f, "Failed to deserialize response: {}", e),
+            OllamaError::Io(e) => write!(f, "I/O error: {}", e),
+            OllamaError::Stream(e) => write!(f, "Stream error: {}", e),
+            OllamaError::InvalidResponse(e) => write!(f, "Invalid response: {}", e),
+            OllamaError::ToolLoopLimit(n) => {
+                write!(f, "Model was still calling tools after {} rounds", n)
+            }
+            OllamaError::DimensionMismatch { left, right } => {
+                write!(f, "Vectors have different lengths: {} and {}", left, right)
+            }
+            OllamaError::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            OllamaError::ToolsNotSupported(model) => {
+                write!(
+                    f,
+                    "Tools are registered but {} does not support them",
+                    model
+                )
+            }
+        }
+    }
+}
+
+impl Error for OllamaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OllamaError::Http(e) => Some(e),
+            OllamaError::Deserialize(e) => Some(e),
+            OllamaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl OllamaError {
+    fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            OllamaError::Http(e) => e.is_connect() || e.is_timeout(),
+            #[cfg(target_arch = "wasm32")]
+            OllamaError::Http(e) => e.is_timeout(),
+            OllamaError::Status { code, .. } => matches!(code, 429 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for OllamaError {
+    fn from(e: reqwest::Error) -> Self {
+        OllamaError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for OllamaError {
+    fn from(e: serde_json::Error) -> Self {
+        OllamaError::Deserialize(e)
+    }
+}
+
+impl From<std::io::Error> for OllamaError {
+    fn from(e: std::io::Error) -> Self {
+        OllamaError::Io(e)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
-    pub role: String,
+    pub role: Role,
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    /// On a tool response, the `id` of the call it answers, when the server
+    /// gave one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            images: None,
+            tool_calls: None,
+            thinking: None,
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn tool(content: impl Into<String>) -> Self {
+        Self::new(Role::Tool, content)
+    }
+
+    pub fn with_images(mut self, images: Vec<String>) -> Self {
+        self.images = Some(images);
+        self
+    }
+
+    /// Encodes `images` and appends them to this message's images, so each
+    /// message in a conversation can carry its own pictures.
+    pub fn add_images(&mut self, images: Vec<ImageSource>) -> Result<(), OllamaError> {
+        let encoded_images = images
+            .iter()
+            .map(ImageSource::encode)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.images
+            .get_or_insert_with(Vec::new)
+            .extend(encoded_images);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    /// Read from disk when the request is sent. Not available on wasm; use
+    /// `Bytes` there.
+    #[cfg(not(target_arch = "wasm32"))]
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+    /// Already base64-encoded, optionally as a `data:image/...;base64,` URL.
+    Base64(String),
+}
+
+impl ImageSource {
+    pub fn encode(&self) -> Result<String, OllamaError> {
+        match self {
+            ImageSource::Base64(data) => match data.strip_prefix("data:") {
+                Some(url) => url
+                    .split_once(";base64,")
+                    .map(|(_, data)| data.to_string())
+                    .ok_or_else(|| {
+                        OllamaError::InvalidInput("Only base64 data URLs are supported".to_string())
+                    }),
+                None => Ok(data.clone()),
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            ImageSource::Path(path) => Ok(general_purpose::STANDARD.encode(std::fs::read(path)?)),
+            ImageSource::Bytes(bytes) => Ok(general_purpose::STANDARD.encode(bytes)),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<String> for ImageSource {
+    fn from(path: String) -> Self {
+        ImageSource::Path(PathBuf::from(path))
+    }
+}
+
+impl From<Vec<u8>> for ImageSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        ImageSource::Bytes(bytes)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ToolCall {
+    /// Set by servers that identify each call; echoed back as the tool
+    /// response's `tool_call_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub function: Function,
 }
 
+impl ToolCall {
+    /// Deserializes the call's arguments into `T`.
+    pub fn parse_args<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.function.arguments)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Function {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// Empty on a streamed chunk that only continues an earlier call.
+    #[serde(default)]
     pub name: String,
+    /// Some models send the arguments as a JSON string, which is parsed on
+    /// the way in when it holds an object or array. Anything else, such as
+    /// a streamed fragment like `"5"`, stays a string.
+    #[serde(default, deserialize_with = "deserialize_arguments")]
     pub arguments: serde_json::Value,
 }
 
+fn deserialize_arguments<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(parse_string_arguments(value))
+}
+
+fn parse_string_arguments(value: serde_json::Value) -> serde_json::Value {
+    if let serde_json::Value::String(text) = &value
+        && let Ok(parsed) = serde_json::from_str::<serde_json::Value>(text)
+        && (parsed.is_object() || parsed.is_array())
+    {
+        return parsed;
+    }
+    value
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ChatResponse {
     pub message: Message,
     pub done: bool,
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    #[serde(flatten)]
+    pub metrics: Metrics,
+    /// Fields this crate doesn't model yet. Must stay the last flattened
+    /// field so it only collects what the others leave.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug)]
+impl ChatResponse {
+    /// True if generation stopped because it hit `num_predict` or the
+    /// context limit rather than finishing on its own.
+    pub fn was_truncated(&self) -> bool {
+        self.done_reason.as_deref() == Some("length")
+    }
+}
+
+/// Token counts and timings reported on the final chunk of a response.
+/// Durations are in nanoseconds.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Metrics {
+    #[serde(default)]
+    pub total_duration: Option<u64>,
+    #[serde(default)]
+    pub load_duration: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+}
+
+impl Metrics {
+    /// Prompt and generated tokens together, or `None` if the server
+    /// reported neither.
+    pub fn total_tokens(&self) -> Option<u64> {
+        match (self.prompt_eval_count, self.eval_count) {
+            (None, None) => None,
+            (prompt, generated) => Some(prompt.unwrap_or(0) + generated.unwrap_or(0)),
+        }
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        match (self.eval_count, self.eval_duration) {
+            (Some(count), Some(duration)) if duration > 0 => {
+                Some(count as f64 / (duration as f64 / 1_000_000_000.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct ChatStreamItem {
     pub content: String,
+    pub thinking: Option<String>,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub done: bool,
+    pub done_reason: Option<String>,
+    pub metrics: Option<Metrics>,
+    /// Response fields this crate doesn't model yet.
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug)]
-pub struct PullProgress {
-    pub status: String,
-    pub digest: Option<String>,
-    pub total: Option<u64>,
-    pub completed: Option<u64>,
+impl ChatStreamItem {
+    /// True on a final chunk whose generation was cut off by `num_predict`
+    /// or the context limit.
+    pub fn was_truncated(&self) -> bool {
+        self.done_reason.as_deref() == Some("length")
+    }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Model {
-    pub name: String,
-    pub modified_at: String,
-    pub size: u64,
+/// A chat stream item together with everything received before it. See
+/// `ChatStreamExt::accumulate`.
+#[derive(Debug, Clone)]
+pub struct ChatDelta {
+    pub delta: String,
+    pub thinking: Option<String>,
+    pub text: String,
+    pub tool_calls_started: bool,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    pub done: bool,
+    pub done_reason: Option<String>,
+    pub metrics: Option<Metrics>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ModelInfo {
-    pub license: String,
-    pub modelfile: String,
-    pub parameters: String,
-    pub template: String,
+pub trait ChatStreamExt {
+    /// Turns a chat stream into one that also carries the reply accumulated
+    /// so far, for UIs that redraw the whole message on every chunk.
+    fn accumulate(self) -> ResponseStream<ChatDelta>;
+
+    /// Ends the stream as soon as the reply contains one of `stops`, which
+    /// closes the connection. The final item is marked `done` and its content
+    /// stops just before the match. Text that could be the start of a stop
+    /// string is held back until it's clear it isn't.
+    fn stop_at(self, stops: Vec<String>) -> ResponseStream<ChatStreamItem>;
+
+    /// Removes one leading space from the first chunk that has content. Some
+    /// models start every reply with one.
+    fn trim_leading_space(self) -> ResponseStream<ChatStreamItem>;
 }
 
-#[derive(Deserialize, Debug)]
-struct ListModelsResponse {
-    models: Vec<Model>,
+impl ChatStreamExt for ResponseStream<ChatStreamItem> {
+    fn accumulate(self) -> ResponseStream<ChatDelta> {
+        Box::pin(self.scan(
+            (String::new(), false),
+            |(text, tool_calls_started), item| {
+                let item = item.map(|item| {
+                    text.push_str(&item.content);
+                    *tool_calls_started |= item.tool_calls.is_some();
+                    ChatDelta {
+                        delta: item.content,
+                        thinking: item.thinking,
+                        text: text.clone(),
+                        tool_calls_started: *tool_calls_started,
+                        tool_calls: item.tool_calls,
+                        done: item.done,
+                        done_reason: item.done_reason,
+                        metrics: item.metrics,
+                    }
+                });
+                futures_util::future::ready(Some(item))
+            },
+        ))
+    }
+
+    fn stop_at(self, stops: Vec<String>) -> ResponseStream<ChatStreamItem> {
+        let stops: Vec<String> = stops.into_iter().filter(|stop| !stop.is_empty()).collect();
+        Box::pin(futures_util::stream::unfold(
+            Some((self, String::new())),
+            move |state| {
+                let stops = stops.clone();
+                async move {
+                    let (mut stream, mut pending) = state?;
+                    let mut item = match stream.next().await {
+                        Some(Ok(item)) => item,
+                        Some(Err(e)) => return Some((Err(e), Some((stream, pending)))),
+                        // The stream ended without a final chunk; don't lose
+                        // text that was being held back.
+                        None if !pending.is_empty() => {
+                            let item = ChatStreamItem {
+                                content: pending,
+                                ..Default::default()
+                            };
+                            return Some((Ok(item), None));
+                        }
+                        None => return None,
+                    };
+                    pending.push_str(&item.content);
+
+                    let stop_match = stops
+                        .iter()
+                        .filter_map(|stop| pending.find(stop.as_str()))
+                        .min();
+                    if let Some(index) = stop_match {
+                        pending.truncate(index);
+                        item.content = pending;
+                        item.done = true;
+                        item.done_reason = Some("stop".to_string());
+                        return Some((Ok(item), None));
+                    }
+                    if item.done {
+                        item.content = pending;
+                        return Some((Ok(item), None));
+                    }
+
+                    let held = stops
+                        .iter()
+                        .map(|stop| partial_suffix_len(&pending, stop))
+                        .max()
+                        .unwrap_or(0);
+                    let rest = pending.split_off(pending.len() - held);
+                    item.content = pending;
+                    Some((Ok(item), Some((stream, rest))))
+                }
+            },
+        ))
+    }
+
+    fn trim_leading_space(self) -> ResponseStream<ChatStreamItem> {
+        Box::pin(self.scan(false, |seen_content, item| {
+            let item = item.map(|mut item| {
+                if !*seen_content && !item.content.is_empty() {
+                    *seen_content = true;
+                    if item.content.starts_with(' ') {
+                        item.content.remove(0);
+                    }
+                }
+                item
+            });
+            futures_util::future::ready(Some(item))
+        }))
+    }
 }
 
-pub struct Tool {
-    pub name: String,
-    pub description: String,
-    pub parameters: serde_json::Value,
-    pub function: Box<dyn Fn(serde_json::Value) -> String + Send + Sync>,
+// Length of the longest end of `text` that is a proper prefix of `stop`.
+fn partial_suffix_len(text: &str, stop: &str) -> usize {
+    (1..stop.len())
+        .rev()
+        .filter(|&len| stop.is_char_boundary(len))
+        .find(|&len| text.ends_with(&stop[..len]))
+        .unwrap_or(0)
 }
 
-impl Tool {
-    fn to_json(&self) -> serde_json::Value {
-        json!({
-            "type": "function",
-            "function": {
-                "name": self.name,
-                "description": self.description,
-                "parameters": self.parameters,
-            }
-        })
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_gpu: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub main_gpu: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_vram: Option<bool>,
 }
 
-pub struct OllamaClient {
-    client: Client,
-    pub endpoint: String,
-    pub model: String,
-    tools: Vec<Tool>,
+#[derive(Debug, Clone, Default)]
+pub struct GenerateRequest {
+    pub prompt: String,
+    pub model: Option<String>,
+    pub system: Option<String>,
+    pub context: Option<Vec<i64>>,
+    pub format: Option<serde_json::Value>,
+    pub raw: bool,
+    pub suffix: Option<String>,
+    pub options: Option<Options>,
+    pub think: Option<bool>,
+    pub images: Vec<ImageSource>,
 }
 
-impl OllamaClient {
-    pub fn new(endpoint: String, model: String) -> Self {
+impl GenerateRequest {
+    pub fn new(prompt: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
-            endpoint,
-            model,
-            tools: Vec::new(),
+            prompt: prompt.into(),
+            ..Default::default()
         }
     }
 
-    pub fn add_tool(&mut self, tool: Tool) {
-        self.tools.push(tool);
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
     }
 
-    pub async fn list_local_models(&self) -> Result<Vec<Model>, Box<dyn Error>> {
-        let response = self
-            .client
-            .get(&format!("{}/api/tags", self.endpoint))
-            .send()
-            .await?
-            .json::<ListModelsResponse>()
-            .await?;
-        Ok(response.models)
+    pub fn system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
     }
 
-    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, Box<dyn Error>> {
-        let response = self
-            .client
-            .post(&format!("{}/api/show", self.endpoint))
-            .json(&json!({ "name": model_name }))
-            .send()
-            .await?
-            .json::<ModelInfo>()
-            .await?;
-        Ok(response)
+    /// Continues from a previous response's `context` instead of resending
+    /// the earlier prompt and reply.
+    pub fn context(mut self, context: Vec<i64>) -> Self {
+        self.context = Some(context);
+        self
     }
 
-    pub async fn pull_model(&self, model_name: &str) -> Result<(), Box<dyn Error>> {
-        println!("Pulling model: {}", model_name);
-        let mut stream = self.pull_model_stream(model_name).await?;
-
-        while let Some(progress) = stream.next().await {
-            let progress = progress.map_err(|e| format!("Stream error: {}", e))?;
-            println!("{}", progress.status);
-        }
-        Ok(())
+    pub fn format(mut self, format: serde_json::Value) -> Self {
+        self.format = Some(format);
+        self
     }
 
-    pub async fn pull_model_stream(
-        &self,
-        model_name: &str,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress, String>> + Send>>, Box<dyn Error>>
-    {
-        let stream = self
-            .client
-            .post(&format!("{}/api/pull", self.endpoint))
-            .json(&json!({ "name": model_name, "stream": true }))
-            .send()
-            .await?
-            .bytes_stream();
+    /// Sends the prompt to the model exactly as given. The server skips the
+    /// model's template, so `system` is ignored and no context is returned.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
 
-        let stream = stream.map(
-            |item| -> Result<Vec<Result<PullProgress, String>>, Box<dyn Error>> {
-                let chunk = item?;
-                let lines = chunk.split(|&b| b == b'\n');
-                let mut results = Vec::new();
+    /// Overrides the client's `set_think` for this request.
+    pub fn think(mut self, think: bool) -> Self {
+        self.think = Some(think);
+        self
+    }
 
-                for line in lines {
-                    if line.is_empty() {
-                        continue;
-                    }
+    /// Options for this request only, layered over the client's options.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = Some(options);
+        self
+    }
 
-                    let line_str = String::from_utf8_lossy(line);
-                    match serde_json::from_str::<serde_json::Value>(&line_str) {
-                        Ok(json) => {
-                            results.push(Ok(PullProgress {
-                                status: json
-                                    .get("status")
-                                    .and_then(|s| s.as_str())
-                                    .unwrap_or("")
-                                    .to_string(),
-                                digest: json
-                                    .get("digest")
-                                    .and_then(|s| s.as_str())
-                                    .map(|s| s.to_string()),
-                                total: json.get("total").and_then(|n| n.as_u64()),
-                                completed: json.get("completed").and_then(|n| n.as_u64()),
-                            }));
-                        }
-                        Err(_) => {
-                            results.push(Ok(PullProgress {
-                                status: line_str.to_string(),
-                                digest: None,
-                                total: None,
-                                completed: None,
-                            }));
-                        }
-                    }
-                }
+    /// Text that comes after the completion, for fill-in-the-middle with
+    /// code models: `prompt` is the code before the cursor.
+    pub fn suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.suffix = Some(suffix.into());
+        self
+    }
 
-                Ok(results)
-            },
-        );
+    /// Images for multimodal models, encoded when the request is sent.
+    pub fn images(mut self, images: Vec<ImageSource>) -> Self {
+        self.images.extend(images);
+        self
+    }
+}
 
-        let flattened_stream = stream
-            .map(
-                |result: Result<Vec<Result<PullProgress, String>>, Box<dyn Error>>| match result {
-                    Ok(items) => futures_util::stream::iter(items),
-                    Err(e) => futures_util::stream::iter(vec![Err(e.to_string())]),
-                },
-            )
-            .flatten();
+impl From<&str> for GenerateRequest {
+    fn from(prompt: &str) -> Self {
+        Self::new(prompt)
+    }
+}
 
-        Ok(Box::pin(flattened_stream))
+impl From<String> for GenerateRequest {
+    fn from(prompt: String) -> Self {
+        Self::new(prompt)
     }
+}
 
-    pub async fn send_chat_request_with_images(
-        &self,
-        messages: &[Message],
-        image_paths: Vec<String>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
-        let mut encoded_images = Vec::new();
-        for image_path in image_paths {
-            let image_bytes = std::fs::read(image_path)?;
-            encoded_images.push(general_purpose::STANDARD.encode(image_bytes));
-        }
+#[derive(Deserialize, Debug, Clone)]
+pub struct GenerateResponse {
+    pub response: String,
+    #[serde(default)]
+    pub thinking: Option<String>,
+    pub done: bool,
+    #[serde(default)]
+    pub context: Option<Vec<i64>>,
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    /// Only filled in on the final chunk.
+    #[serde(flatten)]
+    pub metrics: Metrics,
+    /// Response fields this crate doesn't model yet. Must stay the last
+    /// flattened field so it only collects what the others leave.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
 
-        let mut messages_with_images = messages.to_vec();
-        if let Some(last_message) = messages_with_images.last_mut() {
-            last_message.images = Some(encoded_images);
-        }
+impl GenerateResponse {
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        self.metrics.tokens_per_second()
+    }
 
-        self.send_chat_request(&messages_with_images).await
+    /// A request for `prompt` that continues where this response left off,
+    /// using the token context the server returned.
+    pub fn follow_up(&self, prompt: impl Into<String>) -> GenerateRequest {
+        GenerateRequest {
+            context: self.context.clone(),
+            ..GenerateRequest::new(prompt)
+        }
     }
+}
 
-    pub async fn send_chat_request(
-        &self,
-        messages: &[Message],
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
-        let mut full_response = String::new();
-        let mut tool_calls: Option<Vec<ToolCall>> = None;
-        let mut stream = self.send_chat_request_stream(messages).await?;
+#[derive(Deserialize, Debug, Clone)]
+pub struct PullProgress {
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
 
-        while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
-            if !item.content.is_empty() {
-                print!("{}", item.content);
-                std::io::stdout().flush()?;
-                full_response.push_str(&item.content);
-            }
-            if let Some(tc) = item.tool_calls {
-                tool_calls = Some(tc);
-            }
-            if item.done {
-                println!();
-                return Ok((full_response, tool_calls));
+impl PullProgress {
+    /// How much of the current layer is done, from 0 to 100. `None` when the
+    /// server hasn't reported a size.
+    pub fn percentage(&self) -> Option<f64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => {
+                Some(completed as f64 / total as f64 * 100.0)
             }
+            _ => None,
         }
-        Ok((full_response, tool_calls))
     }
+}
 
-    pub async fn send_chat_request_stream(
-        &self,
-        messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>>
-    {
-        let mut request_body = json!({
-            "model": self.model,
-            "messages": messages,
-            "stream": true,
-        });
+impl fmt::Display for PullProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.percentage() {
+            Some(percentage) => write!(f, "{} {:.1}%", self.status, percentage),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
 
-        if !self.tools.is_empty() {
+/// Request body for `/api/create`. Newer servers build the model from `from`
+/// and `files`, older ones only understand `modelfile`;
+/// [`CreateModelRequest::from_modelfile`] fills in both.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CreateModelRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modelfile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl CreateModelRequest {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_model(mut self, from: impl Into<String>) -> Self {
+        self.from = Some(from.into());
+        self
+    }
+
+    pub fn files(mut self, files: HashMap<String, String>) -> Self {
+        self.files = Some(files);
+        self
+    }
+
+    pub fn from_modelfile(model: impl Into<String>, modelfile: &str) -> Self {
+        let mut request = Self::new(model);
+        request.modelfile = Some(modelfile.to_string());
+
+        let mut parameters = serde_json::Map::new();
+        let mut lines = modelfile.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = read_modelfile_value(rest.trim(), &mut lines);
+
+            match command.to_ascii_uppercase().as_str() {
+                "FROM" => request.from = Some(value),
+                "SYSTEM" => request.system = Some(value),
+                "TEMPLATE" => request.template = Some(value),
+                "LICENSE" => request.license = Some(value),
+                "PARAMETER" => {
+                    let (key, raw) = value
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((&value, ""));
+                    let raw = raw.trim().trim_matches('"');
+                    let parsed = serde_json::from_str::<serde_json::Value>(raw)
+                        .ok()
+                        .filter(|v| v.is_number() || v.is_boolean())
+                        .unwrap_or_else(|| json!(raw));
+                    if key == "stop" {
+                        let stops = parameters.entry(key).or_insert_with(|| json!([]));
+                        if let Some(stops) = stops.as_array_mut() {
+                            stops.push(parsed);
+                        }
+                    } else {
+                        parameters.insert(key.to_string(), parsed);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !parameters.is_empty() {
+            request.parameters = Some(parameters);
+        }
+        request
+    }
+}
+
+// Parses timestamps like `2024-05-01T10:20:30.123456789-07:00`.
+fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+
+    let (time, offset_seconds) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let split = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(split);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let (hours, minutes) = (hours.parse::<i64>().ok()?, minutes.parse::<i64>().ok()?);
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        (time, sign * (hours * 3600 + minutes * 60))
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time_parts = time.splitn(3, ':');
+    let hour: i64 = time_parts.next()?.parse().ok()?;
+    let minute: i64 = time_parts.next()?.parse().ok()?;
+    let second: i64 = time_parts.next()?.parse().ok()?;
+    // RFC 3339 years have four digits, which also keeps the arithmetic
+    // below from overflowing.
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    } else {
+        let digits = &fraction[..fraction.len().min(9)];
+        digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32)
+    };
+
+    // Days since the Unix epoch for a proleptic Gregorian date.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset_seconds;
+    let whole = Duration::from_secs(seconds.unsigned_abs());
+    let time = if seconds >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(whole)?
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(whole)?
+    };
+    time.checked_add(Duration::from_nanos(nanos.into()))
+}
+
+fn read_modelfile_value<'a>(rest: &str, lines: &mut impl Iterator<Item = &'a str>) -> String {
+    if let Some(start) = rest.strip_prefix("\"\"\"") {
+        if let Some(end) = start.find("\"\"\"") {
+            return start[..end].to_string();
+        }
+        let mut value = start.to_string();
+        for line in lines.by_ref() {
+            value.push('\n');
+            if let Some(end) = line.find("\"\"\"") {
+                value.push_str(&line[..end]);
+                break;
+            }
+            value.push_str(line);
+        }
+        return value.trim_start_matches('\n').to_string();
+    }
+    match rest.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        Some(unquoted) => unquoted.to_string(),
+        None => rest.to_string(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Model {
+    pub name: String,
+    pub modified_at: String,
+    pub size: u64,
+    #[serde(default)]
+    pub details: Option<ModelDetails>,
+}
+
+/// Comparison and filter helpers for picking models out of
+/// `list_local_models`, e.g. `models.sort_by(Model::cmp_size)`.
+impl Model {
+    /// Orders smallest first.
+    pub fn cmp_size(&self, other: &Self) -> Ordering {
+        self.size.cmp(&other.size)
+    }
+
+    /// Orders least recently modified first.
+    pub fn cmp_modified(&self, other: &Self) -> Ordering {
+        match (self.modified_at_time(), other.modified_at_time()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => self.modified_at.cmp(&other.modified_at),
+        }
+    }
+
+    /// Parses the RFC 3339 `modified_at` timestamp the server sends.
+    pub fn modified_at_time(&self) -> Result<SystemTime, OllamaError> {
+        parse_rfc3339(&self.modified_at).ok_or_else(|| {
+            OllamaError::InvalidResponse(format!("Invalid timestamp: {}", self.modified_at))
+        })
+    }
+
+    /// Case-insensitive substring match on the model name.
+    pub fn name_contains(&self, query: &str) -> bool {
+        self.name.to_lowercase().contains(&query.to_lowercase())
+    }
+
+    pub fn is_family(&self, family: &str) -> bool {
+        self.details.as_ref().is_some_and(|details| {
+            details.family == family
+                || details
+                    .families
+                    .as_ref()
+                    .is_some_and(|families| families.iter().any(|f| f == family))
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RunningModel {
+    pub name: String,
+    pub size: u64,
+    pub size_vram: u64,
+    pub expires_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModelInfo {
+    pub license: String,
+    pub modelfile: String,
+    pub parameters: String,
+    pub template: String,
+    #[serde(default)]
+    pub model_info: Option<serde_json::Value>,
+    #[serde(default)]
+    pub details: Option<ModelDetails>,
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ModelDetails {
+    pub format: String,
+    pub family: String,
+    pub families: Option<Vec<String>>,
+    pub parameter_size: String,
+    pub quantization_level: String,
+}
+
+impl ModelInfo {
+    /// Whether the server lists `capability` (e.g. `"tools"`, `"vision"`,
+    /// `"embedding"`) for this model. Servers too old to report capabilities
+    /// always give `false`; check `capabilities.is_some()` to tell them apart.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.iter().any(|c| c == capability))
+    }
+
+    pub fn supports_tools(&self) -> bool {
+        self.has_capability("tools")
+    }
+
+    pub fn supports_vision(&self) -> bool {
+        self.has_capability("vision")
+    }
+
+    /// Renders `messages` through this model's prompt template, giving the
+    /// prompt the server would build, except that images aren't tagged and
+    /// old messages aren't dropped to fit the context window. See
+    /// `render_template` for what's supported.
+    pub fn render_prompt(&self, messages: &[Message]) -> Result<String, OllamaError> {
+        render_template(&self.template, messages)
+    }
+
+    /// The maximum context length the model was trained with, read from the
+    /// `<architecture>.context_length` entry of `model_info`.
+    pub fn context_length(&self) -> Option<u64> {
+        let info = self.model_info.as_ref()?.as_object()?;
+        if let Some(architecture) = info.get("general.architecture").and_then(|a| a.as_str())
+            && let Some(length) = info
+                .get(&format!("{}.context_length", architecture))
+                .and_then(|n| n.as_u64())
+        {
+            return Some(length);
+        }
+        info.iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, n)| n.as_u64())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EmbeddingsResponse {
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EmbedResponse {
+    pub embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub raw: String,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Option<Self> {
+        let raw = version.trim();
+        let core = raw
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("");
+        let mut parts = core.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+        let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+
+        Some(Self {
+            major,
+            minor,
+            patch,
+            raw: raw.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct VersionResponse {
+    version: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListModelsResponse {
+    models: Vec<Model>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListRunningModelsResponse {
+    models: Vec<RunningModel>,
+}
+
+#[derive(Clone)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+    pub function: ToolFunction,
+}
+
+/// The streams returned by the streaming methods. They are `Send` except on
+/// wasm, where the underlying HTTP response isn't.
+#[cfg(not(target_arch = "wasm32"))]
+pub type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, OllamaError>> + Send>>;
+#[cfg(target_arch = "wasm32")]
+pub type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, OllamaError>>>>;
+
+#[cfg(not(target_arch = "wasm32"))]
+trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+#[cfg(target_arch = "wasm32")]
+trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+/// The function behind a tool. It's reference counted, so cloning a `Tool`
+/// to register it on several clients shares the same function.
+#[derive(Clone)]
+pub enum ToolFunction {
+    Sync(Arc<dyn Fn(serde_json::Value) -> Result<String, String> + Send + Sync>),
+    Async(Arc<dyn Fn(serde_json::Value) -> ToolFuture + Send + Sync>),
+}
+
+/// Describes the JSON Schema of a tool's arguments. Derive it with
+/// `#[derive(ToolParameters)]` from `ollama-rust-macros`.
+pub trait ToolParameters: DeserializeOwned {
+    fn parameters() -> serde_json::Value;
+}
+
+impl Tool {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+        function: impl Fn(serde_json::Value) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self::fallible(name, description, parameters, move |args| {
+            Ok(function(args))
+        })
+    }
+
+    pub fn fallible(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+        function: impl Fn(serde_json::Value) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            function: ToolFunction::Sync(Arc::new(function)),
+        }
+    }
+
+    pub fn new_async<F>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+        function: impl Fn(serde_json::Value) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            function: ToolFunction::Async(Arc::new(move |args| Box::pin(function(args)))),
+        }
+    }
+
+    /// Builds a tool whose schema comes from `T` and whose function receives
+    /// the arguments already deserialized into `T`.
+    pub fn typed<T: ToolParameters>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        function: impl Fn(T) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self::fallible(name, description, T::parameters(), move |args| {
+            let args =
+                serde_json::from_value(args).map_err(|e| format!("Invalid arguments: {}", e))?;
+            Ok(function(args))
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": self.parameters,
+            }
+        })
+    }
+}
+
+/// How often to retry a request that failed to connect or got a transient
+/// server error (429, 502, 503, 504). The delay doubles after each attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A rough token count for `messages`, without asking the server: four
+/// characters per token, plus a few tokens per message for the role markup
+/// and a fixed cost per image. Real counts depend on the model's tokenizer,
+/// so leave some headroom when comparing against a context length.
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(approximate_tokens).sum()
+}
+
+/// Cosine similarity of two embeddings, in `[-1.0, 1.0]`. Returns 0.0 if
+/// either vector is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Result<f32, OllamaError> {
+    if a.len() != b.len() {
+        return Err(OllamaError::DimensionMismatch {
+            left: a.len(),
+            right: b.len(),
+        });
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(dot / (norm_a * norm_b))
+}
+
+/// Where a local Ollama server listens unless configured otherwise.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:11434";
+const DEFAULT_USER_AGENT: &str = concat!("ollama-rust/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_http_client() -> Client {
+    Client::builder()
+        .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+        .user_agent(DEFAULT_USER_AGENT)
+        .build()
+        .expect("failed to build reqwest client")
+}
+
+// The browser manages connections itself, so there's no connect timeout.
+#[cfg(target_arch = "wasm32")]
+fn default_http_client() -> Client {
+    Client::builder()
+        .user_agent(DEFAULT_USER_AGENT)
+        .build()
+        .expect("failed to build reqwest client")
+}
+
+/// Clones are cheap and share the underlying connection pool; settings
+/// changed on a clone afterwards don't affect the original.
+#[derive(Clone)]
+pub struct OllamaClient {
+    client: Client,
+    /// Base URL of the server, e.g. `http://localhost:11434`. It may include a
+    /// path prefix such as `https://host/ollama` when Ollama sits behind a
+    /// reverse proxy; API paths like `/api/tags` are appended to it as is.
+    pub endpoint: String,
+    pub model: String,
+    tools: Vec<Tool>,
+    bearer_token: Option<String>,
+    options: Option<Options>,
+    keep_alive: Option<String>,
+    unload_after_request: bool,
+    format: Option<serde_json::Value>,
+    max_tool_iterations: usize,
+    json_retries: usize,
+    retry_policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    stream_timeout: Option<Duration>,
+    think: Option<bool>,
+    system_prompt: Option<String>,
+    trim_leading_space: bool,
+    check_tool_support: bool,
+    headers: HeaderMap,
+    user_agent: Option<String>,
+    auto_pull: bool,
+    pull_progress: Option<PullProgressCallback>,
+}
+
+type PullProgressCallback = Arc<dyn Fn(&PullProgress) + Send + Sync>;
+
+/// The result of `chat_with_trace`.
+#[derive(Debug, Clone)]
+pub struct ChatTrace {
+    /// The model's final answer.
+    pub message: Message,
+    /// Each tool call that was run and the content sent back for it.
+    pub tool_calls: Vec<(ToolCall, String)>,
+}
+
+#[derive(Default)]
+struct ChatOverrides<'a> {
+    model: Option<&'a str>,
+    format: Option<&'a serde_json::Value>,
+    options: Option<&'a Options>,
+}
+
+impl Default for OllamaClient {
+    /// A client for `DEFAULT_ENDPOINT` with no model set; pair it with
+    /// `with_model`.
+    fn default() -> Self {
+        Self::new(DEFAULT_ENDPOINT.to_string(), String::new())
+    }
+}
+
+impl OllamaClient {
+    /// `endpoint` is the server's base URL, optionally with a path prefix. A
+    /// trailing slash is ignored.
+    pub fn new(endpoint: String, model: String) -> Self {
+        Self::with_client(default_http_client(), endpoint, model)
+    }
+
+    /// Uses the `OLLAMA_HOST` environment variable as the endpoint, like the
+    /// Ollama CLI does, falling back to `DEFAULT_ENDPOINT` when it's unset.
+    /// Full URLs are used as given. The CLI's short forms `host:port`, `host`
+    /// and `:port` become `http://` URLs, on port 11434 unless another port
+    /// is given.
+    pub fn from_env(model: String) -> Self {
+        let endpoint = std::env::var("OLLAMA_HOST")
+            .ok()
+            .filter(|host| !host.trim().is_empty())
+            .map_or_else(
+                || DEFAULT_ENDPOINT.to_string(),
+                |host| normalize_host(&host),
+            );
+        Self::new(endpoint, model)
+    }
+
+    /// Like `new`, but checks that `endpoint` is an http or https URL first,
+    /// so a typo fails here rather than on the first request.
+    pub fn try_new(endpoint: String, model: String) -> Result<Self, OllamaError> {
+        let url = reqwest::Url::parse(&endpoint).map_err(|e| {
+            OllamaError::InvalidInput(format!("Invalid endpoint {}: {}", endpoint, e))
+        })?;
+        if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+            return Err(OllamaError::InvalidInput(format!(
+                "Endpoint must be an http or https URL, got {}",
+                endpoint
+            )));
+        }
+        Ok(Self::new(endpoint, model))
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn with_client(client: Client, endpoint: String, model: String) -> Self {
+        Self {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model,
+            tools: Vec::new(),
+            bearer_token: None,
+            options: None,
+            keep_alive: None,
+            unload_after_request: false,
+            format: None,
+            max_tool_iterations: 10,
+            json_retries: 0,
+            retry_policy: None,
+            timeout: None,
+            stream_timeout: None,
+            think: None,
+            system_prompt: None,
+            trim_leading_space: false,
+            check_tool_support: false,
+            headers: HeaderMap::new(),
+            user_agent: None,
+            auto_pull: false,
+            pull_progress: None,
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.build_request(method, path, self.timeout)
+    }
+
+    fn stream_request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.build_request(method, path, self.stream_timeout)
+    }
+
+    fn request_keep_alive(&self) -> Option<serde_json::Value> {
+        if self.unload_after_request {
+            return Some(json!(0));
+        }
+        self.keep_alive.as_deref().map(keep_alive_value)
+    }
+
+    fn build_request(
+        &self,
+        method: Method,
+        path: &str,
+        timeout: Option<Duration>,
+    ) -> RequestBuilder {
+        let url = api_url(&self.endpoint, path);
+        let mut request = self.client.request(method, url);
+        // Left to the reqwest client otherwise, so one passed to
+        // `with_client` keeps its own User-Agent.
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(reqwest::header::USER_AGENT, user_agent);
+        }
+        if !self.headers.is_empty() {
+            request = request.headers(self.headers.clone());
+        }
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        request
+    }
+
+    pub fn builder() -> OllamaClientBuilder {
+        OllamaClientBuilder::default()
+    }
+
+    pub fn add_tool(&mut self, tool: Tool) {
+        self.tools.push(tool);
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        self.options = Some(options);
+    }
+
+    /// Sets `Options::stop`, keeping any other options. Generation ends when
+    /// the model produces one of `stop`, and the final chunk then has a
+    /// `done_reason` of `"stop"`.
+    pub fn set_stop_sequences(&mut self, stop: Vec<String>) {
+        self.options.get_or_insert_with(Options::default).stop = Some(stop);
+    }
+
+    /// Sets how long the model stays loaded after chat and generate requests,
+    /// either as a duration like `"5m"` or as seconds like `"300"`. `"0"`
+    /// unloads the model as soon as the call finishes and `"-1"` keeps it
+    /// loaded indefinitely.
+    pub fn set_keep_alive(&mut self, keep_alive: impl Into<String>) {
+        self.keep_alive = Some(keep_alive.into());
+    }
+
+    /// Unloads the model after every chat and generate request, overriding
+    /// `set_keep_alive`. This frees VRAM between calls at the cost of a cold
+    /// load, often several seconds, on each one.
+    pub fn set_unload_after_request(&mut self, unload: bool) {
+        self.unload_after_request = unload;
+    }
+
+    /// Sets the `format` field for chat and generate requests: `json!("json")`
+    /// for JSON mode or a JSON Schema object for structured output.
+    pub fn set_format(&mut self, format: serde_json::Value) {
+        self.format = Some(format);
+    }
+
+    /// Asks reasoning models to think before answering (or not). The
+    /// reasoning comes back separately in the `thinking` fields.
+    pub fn set_think(&mut self, think: bool) {
+        self.think = Some(think);
+    }
+
+    /// Limits how many rounds of tool calls `chat_with_tools` runs before
+    /// giving up. Defaults to 10.
+    pub fn set_max_tool_iterations(&mut self, max_tool_iterations: usize) {
+        self.max_tool_iterations = max_tool_iterations;
+    }
+
+    /// How many more times `send_chat_request_json` asks the model when its
+    /// reply doesn't parse, telling it what was wrong each time. Defaults
+    /// to 0.
+    pub fn set_json_retries(&mut self, retries: usize) {
+        self.json_retries = retries;
+    }
+
+    /// Sets a timeout for requests that return a single response, such as
+    /// list, show or embeddings. Streaming requests are not affected since
+    /// generation can legitimately take minutes; see `set_stream_timeout`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Sets a timeout for streaming requests (chat, generate, pull, push,
+    /// create). It covers the whole stream, so keep it generous.
+    pub fn set_stream_timeout(&mut self, timeout: Duration) {
+        self.stream_timeout = Some(timeout);
+    }
+
+    /// Retries list, show, version, pull and the initial connection of chat
+    /// and generate requests. Streams that have started are never retried.
+    /// On wasm there is no timer, so retries happen without a delay.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = Some(retry_policy);
+    }
+
+    /// A system prompt put in front of every chat. It's skipped when the
+    /// messages already contain a system message, so an explicit one wins.
+    pub fn set_system_prompt(&mut self, prompt: impl Into<String>) {
+        self.system_prompt = Some(prompt.into());
+    }
+
+    /// Strips the single leading space some models put at the start of a
+    /// chat reply. Off by default so replies are passed on byte for byte.
+    pub fn set_trim_leading_space(&mut self, trim: bool) {
+        self.trim_leading_space = trim;
+    }
+
+    /// Before a chat with registered tools, asks `/api/show` whether the model
+    /// supports them and fails with `ToolsNotSupported` if it doesn't. Costs
+    /// an extra request per chat, so it's off by default.
+    pub fn set_check_tool_support(&mut self, check: bool) {
+        self.check_tool_support = check;
+    }
+
+    /// Pulls the model before a chat or generate request if it isn't
+    /// available locally. Off by default, since a pull can download several
+    /// gigabytes.
+    pub fn set_auto_pull(&mut self, auto_pull: bool) {
+        self.auto_pull = auto_pull;
+    }
+
+    /// Called with each progress update of an automatic pull.
+    pub fn set_auto_pull_progress(
+        &mut self,
+        on_progress: impl Fn(&PullProgress) + Send + Sync + 'static,
+    ) {
+        self.pull_progress = Some(Arc::new(on_progress));
+    }
+
+    /// Headers sent with every request, e.g. a tenant ID for a gateway.
+    pub fn set_headers(&mut self, headers: HeaderMap) {
+        self.headers = headers;
+    }
+
+    /// Replaces the reqwest client's User-Agent, which is
+    /// `ollama-rust/<version>` unless a client passed to `with_client` sets
+    /// its own. A User-Agent in `set_headers` takes precedence over this.
+    pub fn set_user_agent(&mut self, user_agent: impl Into<String>) {
+        self.user_agent = Some(user_agent.into());
+    }
+
+    /// Sends `Authorization: Bearer <token>` with every request.
+    pub fn set_bearer_token(&mut self, token: impl Into<String>) {
+        self.bearer_token = Some(token.into());
+    }
+
+    /// Checks that the server is up. Uses its own two-second timeout, not the
+    /// client's, and never retries, so it fails fast.
+    pub async fn health(&self) -> Result<(), OllamaError> {
+        self.build_request(Method::GET, "/api/version", Some(HEALTH_CHECK_TIMEOUT))
+            .send_checked()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn ping(&self) -> bool {
+        self.health().await.is_ok()
+    }
+
+    pub async fn version(&self) -> Result<Version, OllamaError> {
+        let response = self
+            .request(Method::GET, "/api/version")
+            .send_retrying(self.retry_policy.as_ref())
+            .await?
+            .json::<VersionResponse>()
+            .await?;
+        Version::parse(&response.version).ok_or_else(|| {
+            OllamaError::InvalidResponse(format!("Invalid server version: {}", response.version))
+        })
+    }
+
+    pub async fn list_local_models(&self) -> Result<Vec<Model>, OllamaError> {
+        let response = self
+            .request(Method::GET, "/api/tags")
+            .send_retrying(self.retry_policy.as_ref())
+            .await?
+            .json::<ListModelsResponse>()
+            .await?;
+        Ok(response.models)
+    }
+
+    /// Names of the locally pulled models, sorted alphabetically.
+    pub async fn list_model_names(&self) -> Result<Vec<String>, OllamaError> {
+        let mut names: Vec<String> = self
+            .list_local_models()
+            .await?
+            .into_iter()
+            .map(|model| model.name)
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Whether `name` is pulled locally. A name without a tag matches the
+    /// `:latest` tag, so `llama3` finds `llama3:latest`.
+    pub async fn has_model(&self, name: &str) -> Result<bool, OllamaError> {
+        let name = with_default_tag(name);
+        Ok(self
+            .list_local_models()
+            .await?
+            .iter()
+            .any(|model| with_default_tag(&model.name) == name))
+    }
+
+    pub async fn list_running_models(&self) -> Result<Vec<RunningModel>, OllamaError> {
+        let response = self
+            .request(Method::GET, "/api/ps")
+            .send_retrying(self.retry_policy.as_ref())
+            .await?
+            .json::<ListRunningModelsResponse>()
+            .await?;
+        Ok(response.models)
+    }
+
+    /// Whether `name` is loaded in memory right now, checked against
+    /// `/api/ps` so it doesn't load the model. Tags match as in `has_model`.
+    pub async fn is_loaded(&self, name: &str) -> Result<bool, OllamaError> {
+        let name = with_default_tag(name);
+        Ok(self
+            .list_running_models()
+            .await?
+            .iter()
+            .any(|model| with_default_tag(&model.name) == name))
+    }
+
+    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, OllamaError> {
+        let response = self
+            .request(Method::POST, "/api/show")
+            .json(&json!({ "name": model_name }))
+            .send_retrying(self.retry_policy.as_ref())
+            .await?
+            .json::<ModelInfo>()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn embeddings(
+        &self,
+        prompt: &str,
+        model: Option<&str>,
+    ) -> Result<EmbeddingsResponse, OllamaError> {
+        let response = self
+            .request(Method::POST, "/api/embeddings")
+            .json(&json!({
+                "model": model.unwrap_or(&self.model),
+                "prompt": prompt,
+            }))
+            .send_checked()
+            .await?
+            .json::<EmbeddingsResponse>()
+            .await?;
+        Ok(response)
+    }
+
+    /// Embeds every input in one request. The returned `embeddings` are in the
+    /// same order as `inputs`.
+    pub async fn embed_batch(
+        &self,
+        inputs: &[String],
+        model: Option<&str>,
+    ) -> Result<EmbedResponse, OllamaError> {
+        let response = self
+            .request(Method::POST, "/api/embed")
+            .json(&json!({
+                "model": model.unwrap_or(&self.model),
+                "input": inputs,
+            }))
+            .send_checked()
+            .await?
+            .json::<EmbedResponse>()
+            .await?;
+
+        if response.embeddings.len() != inputs.len() {
+            return Err(OllamaError::InvalidResponse(format!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                response.embeddings.len()
+            )));
+        }
+        Ok(response)
+    }
+
+    /// Reads each file, splits it into chunks of `chunk_size` characters that
+    /// overlap by `overlap` characters, and embeds every chunk. Returns one
+    /// `(path, chunk, embedding)` entry per chunk, in file order.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn embed_files(
+        &self,
+        paths: &[PathBuf],
+        chunk_size: usize,
+        overlap: usize,
+    ) -> Result<Vec<(PathBuf, String, Vec<f32>)>, OllamaError> {
+        if chunk_size == 0 || overlap >= chunk_size {
+            return Err(OllamaError::InvalidInput(format!(
+                "Chunk size must be greater than the overlap, got {} and {}",
+                chunk_size, overlap
+            )));
+        }
+
+        let mut results = Vec::new();
+        for path in paths {
+            let text = tokio::fs::read_to_string(path).await?;
+            let chunks = chunk_text(&text, chunk_size, overlap);
+            if chunks.is_empty() {
+                continue;
+            }
+            let embeddings = self.embed_batch(&chunks, None).await?.embeddings;
+            results.extend(
+                chunks
+                    .into_iter()
+                    .zip(embeddings)
+                    .map(|(chunk, embedding)| (path.clone(), chunk, embedding)),
+            );
+        }
+        Ok(results)
+    }
+
+    /// Embeds the query and every document with the client's model and
+    /// returns the documents paired with their similarity to the query,
+    /// most similar first.
+    pub async fn rank_by_similarity(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<(String, f32)>, OllamaError> {
+        let mut inputs = Vec::with_capacity(documents.len() + 1);
+        inputs.push(query.to_string());
+        inputs.extend_from_slice(documents);
+
+        let embeddings = self.embed_batch(&inputs, None).await?.embeddings;
+        let (query_embedding, document_embeddings) = embeddings.split_first().ok_or_else(|| {
+            OllamaError::InvalidResponse("No embedding returned for the query".to_string())
+        })?;
+
+        let mut ranked = documents
+            .iter()
+            .zip(document_embeddings)
+            .map(|(document, embedding)| {
+                Ok((
+                    document.clone(),
+                    cosine_similarity(query_embedding, embedding)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, OllamaError>>()?;
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(ranked)
+    }
+
+    pub async fn delete_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        let response = self
+            .request(Method::DELETE, "/api/delete")
+            .json(&json!({ "name": model_name }))
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::NOT_FOUND => Err(OllamaError::Status {
+                code: 404,
+                body: format!("Model not found: {}", model_name),
+            }),
+            _ => Err(status_error(response).await),
+        }
+    }
+
+    pub async fn copy_model(&self, source: &str, destination: &str) -> Result<(), OllamaError> {
+        let response = self
+            .request(Method::POST, "/api/copy")
+            .json(&json!({ "source": source, "destination": destination }))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(status_error(response).await)
+        }
+    }
+
+    /// Checks whether the server already has the blob with this SHA-256
+    /// digest, given either as `sha256:<hex>` or bare hex.
+    pub async fn blob_exists(&self, digest: &str) -> Result<bool, OllamaError> {
+        let path = blob_path(digest)?;
+        let response = self.request(Method::HEAD, &path).send().await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(status_error(response).await),
+        }
+    }
+
+    /// Uploads `data` as a blob, e.g. a GGUF file to reference from
+    /// `CreateModelRequest::files`. The server rejects the upload if `digest`
+    /// isn't the SHA-256 of `data`.
+    pub async fn create_blob(&self, digest: &str, data: Vec<u8>) -> Result<(), OllamaError> {
+        let path = blob_path(digest)?;
+        let response = self
+            .stream_request(Method::POST, &path)
+            .body(data)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::BAD_REQUEST => Err(OllamaError::Status {
+                code: 400,
+                body: format!("Digest {} does not match the uploaded data", digest),
+            }),
+            _ => Err(status_error(response).await),
+        }
+    }
+
+    /// Loads `model_name` into memory so the first real request doesn't pay
+    /// for it. The model stays loaded for the client's keep-alive, or the
+    /// server default.
+    pub async fn load_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        let mut request_body = json!({ "model": model_name, "stream": false });
+        if let Some(keep_alive) = &self.keep_alive {
+            request_body["keep_alive"] = keep_alive_value(keep_alive);
+        }
+        self.send_empty_generate(request_body).await
+    }
+
+    /// Unloads `model_name` from memory right away, freeing its VRAM.
+    pub async fn unload_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.send_empty_generate(json!({ "model": model_name, "stream": false, "keep_alive": 0 }))
+            .await
+    }
+
+    /// Best-effort way to stop generation on the server, even one started by
+    /// another process. Ollama has no cancel API, so this unloads the model
+    /// instead. That is not guaranteed to cut a running request short: the
+    /// server may let it finish first. It also affects every client using
+    /// the model, and the next request pays for a cold load. To stop your own
+    /// stream, drop it or use a `_cancellable` variant.
+    pub async fn stop_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        self.unload_model(model_name).await
+    }
+
+    async fn send_empty_generate(
+        &self,
+        request_body: serde_json::Value,
+    ) -> Result<(), OllamaError> {
+        // Loading a large model can take a while, so this gets the streaming
+        // timeout rather than the short one.
+        self.stream_request(Method::POST, "/api/generate")
+            .json(&request_body)
+            .send_checked()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn pull_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        info!("Pulling model: {}", model_name);
+        let mut stream = self.pull_model_stream(model_name).await?;
+
+        while let Some(progress) = stream.next().await {
+            let progress = progress?;
+            debug!("{}", progress);
+        }
+        Ok(())
+    }
+
+    /// Pulls `model_name`, calling `on_progress` with every update and the
+    /// percentage done. The percentage is `None` while the server hasn't
+    /// reported a size, e.g. while pulling the manifest.
+    pub async fn pull_model_with_progress(
+        &self,
+        model_name: &str,
+        mut on_progress: impl FnMut(&PullProgress, Option<f64>),
+    ) -> Result<(), OllamaError> {
+        let mut stream = self.pull_model_stream(model_name).await?;
+        while let Some(progress) = stream.next().await {
+            let progress = progress?;
+            on_progress(&progress, progress.percentage());
+        }
+        Ok(())
+    }
+
+    async fn pull_if_missing(&self, model_name: &str) -> Result<(), OllamaError> {
+        if !self.auto_pull || self.has_model(model_name).await? {
+            return Ok(());
+        }
+
+        info!("{} is not available locally, pulling it", model_name);
+        self.pull_model_with_progress(model_name, |progress, _| {
+            if let Some(on_progress) = &self.pull_progress {
+                on_progress(progress);
+            }
+        })
+        .await
+    }
+
+    pub async fn pull_model_stream(
+        &self,
+        model_name: &str,
+    ) -> Result<ResponseStream<PullProgress>, OllamaError> {
+        let response = self
+            .stream_request(Method::POST, "/api/pull")
+            .json(&json!({ "name": model_name, "stream": true }))
+            .send_retrying(self.retry_policy.as_ref())
+            .await?;
+
+        Ok(ndjson_stream(response, parse_progress_line))
+    }
+
+    pub async fn push_model(&self, model_name: &str) -> Result<(), OllamaError> {
+        info!("Pushing model: {}", model_name);
+        let mut stream = self.push_model_stream(model_name).await?;
+
+        while let Some(progress) = stream.next().await {
+            let progress = progress?;
+            debug!("{}", progress.status);
+        }
+        Ok(())
+    }
+
+    pub async fn push_model_stream(
+        &self,
+        model_name: &str,
+    ) -> Result<ResponseStream<PullProgress>, OllamaError> {
+        let response = self
+            .stream_request(Method::POST, "/api/push")
+            .json(&json!({ "name": model_name, "stream": true }))
+            .send_checked()
+            .await?;
+
+        Ok(ndjson_stream(response, parse_progress_line))
+    }
+
+    pub async fn create_model(&self, name: &str, modelfile: &str) -> Result<(), OllamaError> {
+        info!("Creating model: {}", name);
+        let mut stream = self.create_model_stream(name, modelfile).await?;
+
+        while let Some(progress) = stream.next().await {
+            let progress = progress?;
+            debug!("{}", progress.status);
+        }
+        Ok(())
+    }
+
+    pub async fn create_model_stream(
+        &self,
+        name: &str,
+        modelfile: &str,
+    ) -> Result<ResponseStream<PullProgress>, OllamaError> {
+        self.create_model_stream_with(CreateModelRequest::from_modelfile(name, modelfile))
+            .await
+    }
+
+    pub async fn create_model_stream_with(
+        &self,
+        request: CreateModelRequest,
+    ) -> Result<ResponseStream<PullProgress>, OllamaError> {
+        let mut request_body = serde_json::to_value(&request)?;
+        request_body["name"] = json!(request.model);
+        request_body["stream"] = json!(true);
+
+        let response = self
+            .stream_request(Method::POST, "/api/create")
+            .json(&request_body)
+            .send_checked()
+            .await?;
+
+        Ok(ndjson_stream(response, parse_progress_line))
+    }
+
+    /// Attaches the images to the last message before sending. To put images
+    /// on other messages, use `Message::add_images` and `send_chat_request`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_chat_request_with_images(
+        &self,
+        messages: &[Message],
+        image_paths: Vec<String>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let images = image_paths.into_iter().map(ImageSource::from).collect();
+        let messages_with_images = attach_images(messages, images)?;
+        self.send_chat_request(&messages_with_images).await
+    }
+
+    pub async fn send_chat_request_with_image_bytes(
+        &self,
+        messages: &[Message],
+        images: Vec<Vec<u8>>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let images = images.into_iter().map(ImageSource::Bytes).collect();
+        let messages_with_images = attach_images(messages, images)?;
+        self.send_chat_request(&messages_with_images).await
+    }
+
+    pub async fn send_chat_request(
+        &self,
+        messages: &[Message],
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let reply = self.send_chat_message(messages).await?;
+        Ok((reply.content, reply.tool_calls))
+    }
+
+    /// Like `send_chat_request`, but returns the reply as an assistant
+    /// `Message`, with tool calls and thinking, ready to push onto the
+    /// history.
+    pub async fn send_chat_message(&self, messages: &[Message]) -> Result<Message, OllamaError> {
+        Ok(self.send_chat_request_with_metrics(messages).await?.message)
+    }
+
+    /// Like `send_chat_message`, but also returns the final chunk's
+    /// `done_reason` and token counts, e.g. for logging usage per request.
+    pub async fn send_chat_request_with_metrics(
+        &self,
+        messages: &[Message],
+    ) -> Result<ChatResponse, OllamaError> {
+        let stream = self.send_chat_request_stream(messages).await?;
+        collect_chat_stream(stream, |_| Ok(())).await
+    }
+
+    /// Like `send_chat_message`, but asks the server for the whole reply as
+    /// one JSON object instead of streaming it. Nothing arrives until the
+    /// model has finished, so this suits short replies.
+    pub async fn send_chat_message_no_stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Message, OllamaError> {
+        let request_body = self
+            .chat_request_body(messages, &ChatOverrides::default(), false)
+            .await?;
+        let mut response = self
+            .stream_request(Method::POST, "/api/chat")
+            .json(&request_body)
+            .send_retrying(self.retry_policy.as_ref())
+            .await?
+            .json::<ChatResponse>()
+            .await?;
+        if self.trim_leading_space && response.message.content.starts_with(' ') {
+            response.message.content.remove(0);
+        }
+        Ok(response.message)
+    }
+
+    /// Sends every conversation in `batches`, at most `concurrency` at a time,
+    /// and returns the replies in the same order. A failed request only
+    /// fails its own entry.
+    pub async fn chat_many(
+        &self,
+        batches: Vec<Vec<Message>>,
+        concurrency: usize,
+    ) -> Vec<Result<String, OllamaError>> {
+        let mut results: Vec<(usize, Result<String, OllamaError>)> =
+            futures_util::stream::iter(batches.into_iter().enumerate())
+                .map(|(index, messages)| async move {
+                    let result = self
+                        .send_chat_request(&messages)
+                        .await
+                        .map(|(content, _)| content);
+                    (index, result)
+                })
+                .buffer_unordered(concurrency.max(1))
+                .collect()
+                .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Like `send_chat_request`, but prints the reply to stdout as it
+    /// streams in.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send_chat_request_printing(
+        &self,
+        messages: &[Message],
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let stream = self.send_chat_request_stream(messages).await?;
+        let reply = collect_chat_stream(stream, |content| {
+            print!("{}", content);
+            std::io::stdout().flush()
+        })
+        .await?;
+        println!();
+        Ok((reply.message.content, reply.message.tool_calls))
+    }
+
+    /// Like `send_chat_request`, but sends the request to `model` instead of
+    /// the client's default model.
+    pub async fn send_chat_request_with_model(
+        &self,
+        messages: &[Message],
+        model: &str,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let stream = self
+            .send_chat_request_stream_with_model(messages, model)
+            .await?;
+        let reply = collect_chat_stream(stream, |_| Ok(())).await?;
+        Ok((reply.message.content, reply.message.tool_calls))
+    }
+
+    /// Sends the chat with `options` layered over the client's options: any
+    /// field set in `options` wins, the rest come from `set_options`.
+    pub async fn send_chat_request_with_options(
+        &self,
+        messages: &[Message],
+        options: &Options,
+    ) -> Result<(String, Option<Vec<ToolCall>>), OllamaError> {
+        let stream = self
+            .send_chat_request_stream_with_options(messages, options)
+            .await?;
+        let reply = collect_chat_stream(stream, |_| Ok(())).await?;
+        Ok((reply.message.content, reply.message.tool_calls))
+    }
+
+    /// Asks the model for JSON output and deserializes the reply into `T`.
+    /// `schema` is passed as the `format` field; `None` requests plain JSON
+    /// mode. With `set_json_retries`, a reply that doesn't parse is sent back
+    /// along with the error for another try; if every attempt fails, the last
+    /// parse error is returned.
+    pub async fn send_chat_request_json<T: DeserializeOwned>(
+        &self,
+        messages: &[Message],
+        schema: Option<serde_json::Value>,
+    ) -> Result<T, OllamaError> {
+        let format = schema.unwrap_or_else(|| json!("json"));
+        let overrides = ChatOverrides {
+            format: Some(&format),
+            ..Default::default()
+        };
+        let mut messages = messages.to_vec();
+        let mut retries_left = self.json_retries;
+        loop {
+            let stream = self.chat_stream(&messages, &overrides).await?;
+            let reply = collect_chat_stream(stream, |_| Ok(())).await?.message;
+            match serde_json::from_str(&reply.content) {
+                Ok(value) => return Ok(value),
+                Err(e) if retries_left > 0 => {
+                    retries_left -= 1;
+                    warn!("Reply was not the expected JSON, retrying: {}", e);
+                    messages.push(reply);
+                    messages.push(Message::user(format!(
+                        "Your previous reply could not be parsed: {}. Reply again with only valid JSON.",
+                        e
+                    )));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Like `send_chat_request_json`, but uses `T::parameters()` as the
+    /// schema, so a type deriving `ToolParameters` gets one automatically.
+    pub async fn send_chat_request_typed<T: ToolParameters>(
+        &self,
+        messages: &[Message],
+    ) -> Result<T, OllamaError> {
+        self.send_chat_request_json(messages, Some(T::parameters()))
+            .await
+    }
+
+    /// Streams the reply chunk by chunk. A line the server sends that can't be
+    /// parsed is yielded as an `Err` item; the stream keeps going after it.
+    /// An error the server reports mid-stream comes through as
+    /// `OllamaError::Stream`.
+    /// Dropping the stream closes the connection, which makes the server stop
+    /// generating.
+    pub async fn send_chat_request_stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<ResponseStream<ChatStreamItem>, OllamaError> {
+        self.chat_stream(messages, &ChatOverrides::default()).await
+    }
+
+    pub async fn send_chat_request_stream_with_model(
+        &self,
+        messages: &[Message],
+        model: &str,
+    ) -> Result<ResponseStream<ChatStreamItem>, OllamaError> {
+        let overrides = ChatOverrides {
+            model: Some(model),
+            ..Default::default()
+        };
+        self.chat_stream(messages, &overrides).await
+    }
+
+    pub async fn send_chat_request_stream_with_options(
+        &self,
+        messages: &[Message],
+        options: &Options,
+    ) -> Result<ResponseStream<ChatStreamItem>, OllamaError> {
+        let overrides = ChatOverrides {
+            options: Some(options),
+            ..Default::default()
+        };
+        self.chat_stream(messages, &overrides).await
+    }
+
+    /// Like `send_chat_request_stream`, but ends the stream and aborts the
+    /// request as soon as `token` is cancelled.
+    pub async fn send_chat_request_stream_cancellable(
+        &self,
+        messages: &[Message],
+        token: CancellationToken,
+    ) -> Result<ResponseStream<ChatStreamItem>, OllamaError> {
+        let stream = self.send_chat_request_stream(messages).await?;
+        Ok(cancellable(stream, token))
+    }
+
+    async fn chat_request_body(
+        &self,
+        messages: &[Message],
+        overrides: &ChatOverrides<'_>,
+        stream: bool,
+    ) -> Result<serde_json::Value, OllamaError> {
+        let model = overrides.model.unwrap_or(&self.model);
+        self.pull_if_missing(model).await?;
+        if self.check_tool_support && !self.tools.is_empty() {
+            let info = self.show_model_info(model).await?;
+            if info.capabilities.is_none() {
+                warn!("Server did not report capabilities for {}", model);
+            } else if !info.supports_tools() {
+                return Err(OllamaError::ToolsNotSupported(model.to_string()));
+            }
+        }
+
+        let mut request_body = json!({
+            "model": model,
+            "messages": messages,
+            "stream": stream,
+        });
+
+        if let Some(system_prompt) = &self.system_prompt
+            && !messages.iter().any(|message| message.role == Role::System)
+            && let Some(messages) = request_body["messages"].as_array_mut()
+        {
+            messages.insert(0, json!(Message::system(system_prompt.as_str())));
+        }
+
+        if !self.tools.is_empty() {
             let tools_json: Vec<serde_json::Value> =
                 self.tools.iter().map(|t| t.to_json()).collect();
             request_body["tools"] = serde_json::Value::Array(tools_json);
         }
+        if let Some(options) = merged_options(self.options.as_ref(), overrides.options) {
+            request_body["options"] = options;
+        }
+        if let Some(keep_alive) = self.request_keep_alive() {
+            request_body["keep_alive"] = keep_alive;
+        }
+        if let Some(format) = overrides.format.or(self.format.as_ref()) {
+            request_body["format"] = format.clone();
+        }
+        if let Some(think) = self.think {
+            request_body["think"] = json!(think);
+        }
+        Ok(request_body)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        overrides: &ChatOverrides<'_>,
+    ) -> Result<ResponseStream<ChatStreamItem>, OllamaError> {
+        let request_body = self.chat_request_body(messages, overrides, true).await?;
+        let response = self
+            .stream_request(Method::POST, "/api/chat")
+            .json(&request_body)
+            .send_retrying(self.retry_policy.as_ref())
+            .await?;
+
+        let stream = ndjson_stream(response, |line| {
+            serde_json::from_slice::<ChatResponse>(line).map(|chat_response| ChatStreamItem {
+                content: chat_response.message.content,
+                thinking: chat_response.message.thinking,
+                tool_calls: chat_response.message.tool_calls,
+                done: chat_response.done,
+                done_reason: chat_response.done_reason,
+                metrics: chat_response.done.then_some(chat_response.metrics),
+                extra: chat_response.extra,
+            })
+        });
+
+        if self.trim_leading_space {
+            Ok(stream.trim_leading_space())
+        } else {
+            Ok(stream)
+        }
+    }
+
+    pub async fn generate(
+        &self,
+        request: impl Into<GenerateRequest>,
+    ) -> Result<GenerateResponse, OllamaError> {
+        self.generate_with_callback(request, |_| {}).await
+    }
+
+    /// Streams a generation, calling `on_token` with each chunk of text as it
+    /// arrives, and returns the full response along with the final `context`.
+    pub async fn generate_with_callback(
+        &self,
+        request: impl Into<GenerateRequest>,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<GenerateResponse, OllamaError> {
+        let mut full_response = String::new();
+        let mut thinking: Option<String> = None;
+        let mut stream = self.generate_stream(request).await?;
+
+        while let Some(item) = stream.next().await {
+            let item = item?;
+            if !item.response.is_empty() {
+                on_token(&item.response);
+            }
+            full_response.push_str(&item.response);
+            if let Some(chunk) = &item.thinking {
+                thinking.get_or_insert_with(String::new).push_str(chunk);
+            }
+            if item.done {
+                return Ok(GenerateResponse {
+                    response: full_response,
+                    thinking,
+                    done: true,
+                    context: item.context,
+                    done_reason: item.done_reason,
+                    metrics: item.metrics,
+                    extra: item.extra,
+                });
+            }
+        }
+        Ok(GenerateResponse {
+            response: full_response,
+            thinking,
+            done: false,
+            context: None,
+            done_reason: None,
+            metrics: Metrics::default(),
+            extra: serde_json::Map::new(),
+        })
+    }
+
+    fn generate_request_body(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<serde_json::Value, OllamaError> {
+        let model = request.model.as_deref().unwrap_or(&self.model);
+        let mut request_body = json!({
+            "model": model,
+            "prompt": request.prompt,
+            "stream": true,
+        });
+
+        if let Some(system) = request.system {
+            request_body["system"] = json!(system);
+        }
+        if let Some(context) = request.context {
+            request_body["context"] = json!(context);
+        }
+        if request.raw {
+            request_body["raw"] = json!(true);
+        }
+        if let Some(suffix) = request.suffix {
+            request_body["suffix"] = json!(suffix);
+        }
+        if !request.images.is_empty() {
+            let images = request
+                .images
+                .iter()
+                .map(ImageSource::encode)
+                .collect::<Result<Vec<_>, _>>()?;
+            request_body["images"] = json!(images);
+        }
+        if let Some(options) = merged_options(self.options.as_ref(), request.options.as_ref()) {
+            request_body["options"] = options;
+        }
+        if let Some(keep_alive) = self.request_keep_alive() {
+            request_body["keep_alive"] = keep_alive;
+        }
+        if let Some(format) = request.format.or_else(|| self.format.clone()) {
+            request_body["format"] = format;
+        }
+        if let Some(think) = request.think.or(self.think) {
+            request_body["think"] = json!(think);
+        }
+        Ok(request_body)
+    }
+
+    /// Like `generate_stream`, but ends the stream and aborts the request as
+    /// soon as `token` is cancelled.
+    pub async fn generate_stream_cancellable(
+        &self,
+        request: impl Into<GenerateRequest>,
+        token: CancellationToken,
+    ) -> Result<ResponseStream<GenerateResponse>, OllamaError> {
+        let stream = self.generate_stream(request).await?;
+        Ok(cancellable(stream, token))
+    }
+
+    pub async fn generate_stream(
+        &self,
+        request: impl Into<GenerateRequest>,
+    ) -> Result<ResponseStream<GenerateResponse>, OllamaError> {
+        let request = request.into();
+        self.pull_if_missing(request.model.as_deref().unwrap_or(&self.model))
+            .await?;
+        let request_body = self.generate_request_body(request)?;
+
+        let response = self
+            .stream_request(Method::POST, "/api/generate")
+            .json(&request_body)
+            .send_retrying(self.retry_policy.as_ref())
+            .await?;
+
+        Ok(ndjson_stream(response, |line| {
+            serde_json::from_slice::<GenerateResponse>(line)
+        }))
+    }
+
+    /// Sends the conversation and keeps executing the model's tool calls,
+    /// feeding the results back, until it answers without calling a tool.
+    /// Returns that final assistant message. A call to a tool that isn't
+    /// registered is answered with an `Error:` result.
+    pub async fn chat_with_tools(&self, messages: Vec<Message>) -> Result<Message, OllamaError> {
+        Ok(self.chat_with_trace(messages).await?.message)
+    }
+
+    /// Like `chat_with_tools`, but also returns every tool call that was run
+    /// along the way with its result, in order, for logging or debugging.
+    pub async fn chat_with_trace(
+        &self,
+        mut messages: Vec<Message>,
+    ) -> Result<ChatTrace, OllamaError> {
+        let mut tool_calls = Vec::new();
+        for _ in 0..self.max_tool_iterations {
+            let reply = self.send_chat_message(&messages).await?;
+            let calls = match &reply.tool_calls {
+                Some(calls) if !calls.is_empty() => calls.clone(),
+                _ => {
+                    return Ok(ChatTrace {
+                        message: reply,
+                        tool_calls,
+                    });
+                }
+            };
+
+            messages.push(reply);
+            let responses = join_all(calls.iter().map(|call| self.run_tool_call(call))).await;
+            for (call, response) in calls.into_iter().zip(responses) {
+                tool_calls.push((call, response.content.clone()));
+                messages.push(response);
+            }
+        }
+        Err(OllamaError::ToolLoopLimit(self.max_tool_iterations))
+    }
+
+    /// Runs each requested tool and returns one `tool` message per call, in
+    /// the same order and tagged with the call's `id` if it had one. A tool
+    /// that fails or isn't registered produces a message whose content
+    /// starts with `Error:` so the model can see the call did not succeed.
+    /// Async tools can't be run here and are reported as failures; use
+    /// `handle_tool_calls_async`.
+    pub fn handle_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
+        tool_calls
+            .into_iter()
+            .map(|tool_call| {
+                let result = match self.find_tool(&tool_call) {
+                    Some(Tool {
+                        function: ToolFunction::Sync(function),
+                        ..
+                    }) => function(tool_call.function.arguments),
+                    Some(tool) => Err(format!(
+                        "{} is an async tool, use handle_tool_calls_async",
+                        tool.name
+                    )),
+                    None => Err(unknown_tool(&tool_call)),
+                };
+                tool_response(result, tool_call.id)
+            })
+            .collect()
+    }
+
+    /// Like `handle_tool_calls`, but runs the calls concurrently. The returned
+    /// messages are in the same order as `tool_calls`, so models relying on
+    /// positional correspondence still see matching results.
+    pub async fn handle_tool_calls_async(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
+        join_all(
+            tool_calls
+                .iter()
+                .map(|tool_call| self.run_tool_call(tool_call)),
+        )
+        .await
+    }
+
+    fn find_tool(&self, tool_call: &ToolCall) -> Option<&Tool> {
+        self.tools
+            .iter()
+            .find(|t| t.name == tool_call.function.name)
+    }
+
+    // Calls to unregistered tools are answered too, so results still line up
+    // with their calls and the model doesn't repeat them until the loop limit.
+    async fn run_tool_call(&self, tool_call: &ToolCall) -> Message {
+        let arguments = tool_call.function.arguments.clone();
+        let result = match self.find_tool(tool_call).map(|tool| &tool.function) {
+            Some(ToolFunction::Sync(function)) => function(arguments),
+            Some(ToolFunction::Async(function)) => function(arguments).await,
+            None => Err(unknown_tool(tool_call)),
+        };
+        tool_response(result, tool_call.id.clone())
+    }
+}
+
+#[derive(Default)]
+pub struct OllamaClientBuilder {
+    endpoint: Option<String>,
+    model: Option<String>,
+    timeout: Option<Duration>,
+    stream_timeout: Option<Duration>,
+    bearer_token: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+    system_prompt: Option<String>,
+    headers: HeaderMap,
+    user_agent: Option<String>,
+    tools: Vec<Tool>,
+}
+
+impl OllamaClientBuilder {
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn stream_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_timeout = Some(timeout);
+        self
+    }
+
+    pub fn bearer_token(mut self, token: String) -> Self {
+        self.bearer_token = Some(token);
+        self
+    }
+
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn tool(mut self, tool: Tool) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    pub fn build(self) -> OllamaClient {
+        let mut client = OllamaClient::new(
+            self.endpoint
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+            self.model.unwrap_or_default(),
+        );
+        client.timeout = self.timeout;
+        client.stream_timeout = self.stream_timeout;
+        client.tools = self.tools;
+        client.bearer_token = self.bearer_token;
+        client.retry_policy = self.retry_policy;
+        client.system_prompt = self.system_prompt;
+        client.headers = self.headers;
+        client.user_agent = self.user_agent;
+        client
+    }
+}
+
+/// A chat history that grows as you talk to the model. It serializes as the
+/// plain array of messages, the same shape the chat endpoint accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Conversation {
+    messages: Vec<Message>,
+    #[serde(skip)]
+    truncation: Option<TruncationStrategy>,
+}
+
+/// How a `Conversation` trims its history before each request. System
+/// messages are always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Keep the last N non-system messages.
+    KeepLast(usize),
+    /// Keep as many recent messages as fit in roughly this many tokens, as
+    /// counted by `estimate_tokens`. The newest message is kept
+    /// even if it alone is over budget.
+    TokenBudget(usize),
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_system(prompt: impl Into<String>) -> Self {
+        Self {
+            messages: vec![Message::system(prompt)],
+            truncation: None,
+        }
+    }
+
+    pub fn set_truncation(&mut self, strategy: TruncationStrategy) {
+        self.truncation = Some(strategy);
+    }
+
+    pub fn add_user(&mut self, content: &str) {
+        self.messages.push(Message::user(content));
+    }
+
+    pub fn push(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+
+    /// Sends the history so far, appends the assistant's reply to it and
+    /// returns that reply.
+    pub async fn send(&mut self, client: &OllamaClient) -> Result<Message, OllamaError> {
+        self.truncate();
+        let reply = client.send_chat_message(&self.messages).await?;
+        self.messages.push(reply.clone());
+        Ok(reply)
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn to_json(&self) -> Result<String, OllamaError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, OllamaError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), OllamaError> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, OllamaError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Drops every message except the system prompt.
+    pub fn clear(&mut self) {
+        self.messages.retain(|message| message.role == Role::System);
+    }
+
+    /// Applies the truncation strategy, if any, dropping the oldest turns.
+    /// `send` calls this before every request.
+    pub fn truncate(&mut self) {
+        let Some(strategy) = self.truncation else {
+            return;
+        };
+        let (system, mut rest): (Vec<Message>, Vec<Message>) = std::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(|message| message.role == Role::System);
+
+        let keep = match strategy {
+            TruncationStrategy::KeepLast(n) => n.min(rest.len()),
+            TruncationStrategy::TokenBudget(budget) => {
+                let mut remaining =
+                    budget.saturating_sub(system.iter().map(approximate_tokens).sum());
+                let mut keep = 0;
+                for message in rest.iter().rev() {
+                    let tokens = approximate_tokens(message);
+                    if tokens > remaining && keep > 0 {
+                        break;
+                    }
+                    remaining = remaining.saturating_sub(tokens);
+                    keep += 1;
+                }
+                keep
+            }
+        };
+
+        let mut kept = rest.split_off(rest.len() - keep);
+        // A tool result without the assistant message that asked for it
+        // confuses the model, so don't start the window on one.
+        let orphaned = kept
+            .iter()
+            .take_while(|message| message.role == Role::Tool)
+            .count();
+        kept.drain(..orphaned);
+
+        self.messages = system;
+        self.messages.extend(kept);
+    }
+}
+
+/// Keeps the `context` between generate calls, the `/api/generate`
+/// counterpart of `Conversation`.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateSession {
+    context: Option<Vec<i64>>,
+}
+
+impl GenerateSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `request` with the stored context, or as a fresh start if there
+    /// is none yet, and stores the context from the response.
+    pub async fn send(
+        &mut self,
+        client: &OllamaClient,
+        request: impl Into<GenerateRequest>,
+    ) -> Result<GenerateResponse, OllamaError> {
+        let mut request = request.into();
+        if let Some(context) = &self.context {
+            request.context = Some(context.clone());
+        }
+        let response = client.generate(request).await?;
+        if response.context.is_some() {
+            self.context = response.context.clone();
+        }
+        Ok(response)
+    }
+
+    pub fn context(&self) -> Option<&[i64]> {
+        self.context.as_deref()
+    }
+
+    /// Forgets the context, so the next `send` starts over.
+    pub fn reset(&mut self) {
+        self.context = None;
+    }
+}
+
+fn with_default_tag(name: &str) -> String {
+    // Only the last path segment can carry a tag; a colon before it belongs
+    // to a registry port.
+    let last_segment = name.rsplit('/').next().unwrap_or(name);
+    if last_segment.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+fn blob_path(digest: &str) -> Result<String, OllamaError> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(OllamaError::InvalidInput(format!(
+            "Expected a SHA-256 digest, got {}",
+            digest
+        )));
+    }
+    Ok(format!("/api/blobs/sha256:{}", hex.to_ascii_lowercase()))
+}
+
+// Splits on character boundaries, never inside a UTF-8 sequence.
+#[cfg(not(target_arch = "wasm32"))]
+fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let char_count = boundaries.len() - 1;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < char_count {
+        let end = (start + chunk_size).min(char_count);
+        chunks.push(text[boundaries[start]..boundaries[end]].to_string());
+        if end == char_count {
+            break;
+        }
+        start += chunk_size - overlap;
+    }
+    chunks
+}
 
-        let stream = self
-            .client
-            .post(&format!("{}/api/chat", self.endpoint))
-            .json(&request_body)
-            .send()
-            .await?
-            .bytes_stream();
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+const IMAGE_TOKENS: usize = 576;
+
+fn approximate_tokens(message: &Message) -> usize {
+    let images = message.images.as_ref().map_or(0, Vec::len);
+    message.content.chars().count().div_ceil(4) + MESSAGE_OVERHEAD_TOKENS + images * IMAGE_TOKENS
+}
+
+fn attach_images(
+    messages: &[Message],
+    images: Vec<ImageSource>,
+) -> Result<Vec<Message>, OllamaError> {
+    let mut messages_with_images = messages.to_vec();
+    if let Some(last_message) = messages_with_images.last_mut() {
+        last_message.add_images(images)?;
+    }
+    Ok(messages_with_images)
+}
+
+fn unknown_tool(tool_call: &ToolCall) -> String {
+    format!("Unknown tool {}", tool_call.function.name)
+}
+
+fn tool_response(result: Result<String, String>, tool_call_id: Option<String>) -> Message {
+    let content = match result {
+        Ok(content) => content,
+        Err(e) => format!("Error: {}", e),
+    };
+    Message {
+        tool_call_id,
+        ..Message::tool(content)
+    }
+}
+
+async fn collect_chat_stream(
+    mut stream: ResponseStream<ChatStreamItem>,
+    mut on_content: impl FnMut(&str) -> std::io::Result<()>,
+) -> Result<ChatResponse, OllamaError> {
+    let mut reply = ChatResponse {
+        message: Message::assistant(""),
+        done: false,
+        done_reason: None,
+        metrics: Metrics::default(),
+        extra: serde_json::Map::new(),
+    };
+
+    while let Some(item) = stream.next().await {
+        let item = item?;
+        let message = &mut reply.message;
+        if !item.content.is_empty() {
+            on_content(&item.content)?;
+            message.content.push_str(&item.content);
+        }
+        if let Some(thinking) = item.thinking {
+            message
+                .thinking
+                .get_or_insert_with(String::new)
+                .push_str(&thinking);
+        }
+        if let Some(chunk) = item.tool_calls {
+            merge_tool_calls(message.tool_calls.get_or_insert_with(Vec::new), chunk);
+        }
+        if item.done {
+            reply.done = true;
+            reply.done_reason = item.done_reason;
+            reply.metrics = item.metrics.unwrap_or_default();
+            reply.extra = item.extra;
+            break;
+        }
+    }
+    // String arguments only form valid JSON once all their fragments are in.
+    for call in reply.message.tool_calls.iter_mut().flatten() {
+        let arguments = std::mem::take(&mut call.function.arguments);
+        call.function.arguments = parse_string_arguments(arguments);
+    }
+    Ok(reply)
+}
+
+// Ollama normally sends each tool call whole, but calls may be spread over
+// several chunks, so every chunk's calls are appended in order. A call whose
+// `index` matches an earlier one continues it; without an index, a call with
+// no name continues the previous one. Continuations fill in a missing name
+// or id and have their arguments merged in.
+fn merge_tool_calls(tool_calls: &mut Vec<ToolCall>, chunk: Vec<ToolCall>) {
+    for call in chunk {
+        let target = match call.function.index {
+            Some(index) => tool_calls
+                .iter()
+                .position(|existing| existing.function.index == Some(index)),
+            None if call.function.name.is_empty() => tool_calls.len().checked_sub(1),
+            None => None,
+        };
+        let Some(target) = target else {
+            tool_calls.push(call);
+            continue;
+        };
+        let existing = &mut tool_calls[target];
+        if existing.id.is_none() {
+            existing.id = call.id;
+        }
+        if existing.function.name.is_empty() {
+            existing.function.name = call.function.name;
+        }
+        match (&mut existing.function.arguments, call.function.arguments) {
+            (_, serde_json::Value::Null) => {}
+            (serde_json::Value::Object(arguments), serde_json::Value::Object(more)) => {
+                arguments.extend(more)
+            }
+            // A fragment that was itself a whole object or array got parsed,
+            // so write it back out to continue the text.
+            (serde_json::Value::String(arguments), more) => match more {
+                serde_json::Value::String(more) => arguments.push_str(&more),
+                more => arguments.push_str(&more.to_string()),
+            },
+            (arguments, more) => *arguments = more,
+        }
+    }
+}
+
+trait RequestBuilderExt {
+    async fn send_checked(self) -> Result<reqwest::Response, OllamaError>;
+    async fn send_retrying(
+        self,
+        policy: Option<&RetryPolicy>,
+    ) -> Result<reqwest::Response, OllamaError>;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    /// Sends the request and turns a non-success status into
+    /// `OllamaError::Status`, using the server's error message if it sent one.
+    async fn send_checked(self) -> Result<reqwest::Response, OllamaError> {
+        let response = self.send().await?;
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(status_error(response).await)
+        }
+    }
+
+    /// Like `send_checked`, but retries connection failures and transient
+    /// server errors according to `policy`. Only the initial send is
+    /// retried; once a response is returned its body is never re-requested.
+    async fn send_retrying(
+        self,
+        policy: Option<&RetryPolicy>,
+    ) -> Result<reqwest::Response, OllamaError> {
+        let Some(policy) = policy else {
+            return self.send_checked().await;
+        };
+
+        let mut attempt = 1;
+        loop {
+            let Some(request) = self.try_clone() else {
+                return self.send_checked().await;
+            };
+            match request.send_checked().await {
+                Err(e) if attempt < policy.max_attempts && e.is_transient() => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    tokio::time::sleep(policy.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn status_error(response: reqwest::Response) -> OllamaError {
+    let code = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    let body = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => error.error,
+        Err(_) => body,
+    };
+    OllamaError::Status { code, body }
+}
+
+// Per-call options override the client's field by field.
+fn merged_options(
+    client: Option<&Options>,
+    overrides: Option<&Options>,
+) -> Option<serde_json::Value> {
+    let mut merged = serde_json::Map::new();
+    for options in [client, overrides].into_iter().flatten() {
+        if let serde_json::Value::Object(fields) = json!(options) {
+            merged.extend(fields);
+        }
+    }
+    (!merged.is_empty()).then_some(serde_json::Value::Object(merged))
+}
+
+// `endpoint` is public and may have been changed since construction, so a
+// trailing slash is dropped here too.
+fn api_url(endpoint: &str, path: &str) -> String {
+    format!("{}{}", endpoint.trim_end_matches('/'), path)
+}
+
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    // A full URL is used as given, so its scheme's default port applies and
+    // a path prefix survives.
+    if host.contains("://") {
+        return host.to_string();
+    }
+    let (authority, path) = match host.find('/') {
+        Some(index) => host.split_at(index),
+        None => (host, ""),
+    };
+    let authority = if authority.starts_with(':') {
+        format!("localhost{}", authority)
+    } else if authority.is_empty() {
+        "localhost".to_string()
+    } else {
+        authority.to_string()
+    };
+    // An IPv6 address like `[::1]` has colons of its own, so only a colon
+    // after the closing bracket starts a port.
+    let port = if authority.rfind(':') > authority.rfind(']') {
+        ""
+    } else {
+        ":11434"
+    };
+    format!("http://{}{}{}", authority, port, path)
+}
+
+fn keep_alive_value(keep_alive: &str) -> serde_json::Value {
+    match keep_alive.parse::<i64>() {
+        Ok(seconds) => json!(seconds),
+        Err(_) => json!(keep_alive),
+    }
+}
+
+fn parse_progress_line(line: &[u8]) -> Result<PullProgress, serde_json::Error> {
+    serde_json::from_slice(line)
+}
+
+fn cancellable<T: MaybeSend + 'static>(
+    stream: ResponseStream<T>,
+    token: CancellationToken,
+) -> ResponseStream<T> {
+    Box::pin(futures_util::stream::unfold(Some(stream), move |stream| {
+        let token = token.clone();
+        async move {
+            let mut stream = stream?;
+            // Returning None drops the inner stream, which closes the
+            // connection to the server.
+            tokio::select! {
+                _ = token.cancelled() => None,
+                item = stream.next() => item.map(|item| (item, Some(stream))),
+            }
+        }
+    }))
+}
 
-        let stream = stream.map(
-            |item| -> Result<Vec<Result<ChatStreamItem, String>>, Box<dyn Error>> {
-                let chunk = item?;
-                let lines = chunk.split(|&b| b == b'\n');
-                let mut results = Vec::new();
+fn parse_ndjson_line<T>(
+    line: &[u8],
+    parse_line: impl Fn(&[u8]) -> Result<T, serde_json::Error>,
+) -> Result<T, OllamaError> {
+    parse_line(line).map_err(|e| {
+        // The server reports failures after the response has started as a
+        // line with an `error` field. Checking for that only once the typed
+        // parse has failed keeps the common path to a single parse.
+        if let Ok(error) = serde_json::from_slice::<ErrorResponse>(line) {
+            return OllamaError::Stream(error.error);
+        }
+        warn!(
+            "Error parsing response: {}, line: {:?}",
+            e,
+            String::from_utf8_lossy(line)
+        );
+        OllamaError::Deserialize(e)
+    })
+}
+
+fn ndjson_stream<T, F>(response: reqwest::Response, parse_line: F) -> ResponseStream<T>
+where
+    T: MaybeSend + 'static,
+    F: Fn(&[u8]) -> Result<T, serde_json::Error> + MaybeSend + 'static,
+{
+    // A JSON line can be split across network chunks, so bytes are buffered
+    // and only complete, newline-terminated lines are parsed, straight out of
+    // the buffer. Whatever is left when the body ends is treated as a final
+    // line.
+    struct Lines<S, F> {
+        chunks: S,
+        buffer: Vec<u8>,
+        start: usize,
+        finished: bool,
+        parse_line: F,
+    }
+
+    let lines = Lines {
+        chunks: response.bytes_stream(),
+        buffer: Vec::new(),
+        start: 0,
+        finished: false,
+        parse_line,
+    };
+
+    Box::pin(futures_util::stream::unfold(
+        lines,
+        |mut lines| async move {
+            loop {
+                let pending = &lines.buffer[lines.start..];
+                if let Some(offset) = pending.iter().position(|&b| b == b'\n') {
+                    let line = &pending[..offset];
+                    let item = if line.is_empty() {
+                        None
+                    } else {
+                        Some(parse_ndjson_line(line, &lines.parse_line))
+                    };
+                    lines.start += offset + 1;
+                    match item {
+                        Some(item) => return Some((item, lines)),
+                        None => continue,
+                    }
+                }
+                if lines.finished {
+                    return None;
+                }
 
-                for line in lines {
-                    if line.is_empty() {
-                        continue;
+                lines.buffer.drain(..lines.start);
+                lines.start = 0;
+                match lines.chunks.next().await {
+                    Some(Ok(chunk)) => lines.buffer.extend_from_slice(&chunk),
+                    Some(Err(e)) => {
+                        lines.finished = true;
+                        lines.buffer.clear();
+                        return Some((Err(OllamaError::Http(e)), lines));
                     }
-                    match serde_json::from_slice::<ChatResponse>(&line) {
-                        Ok(chat_response) => {
-                            results.push(Ok(ChatStreamItem {
-                                content: chat_response.message.content.clone(),
-                                tool_calls: chat_response.message.tool_calls.clone(),
-                                done: chat_response.done,
-                            }));
+                    None => {
+                        lines.finished = true;
+                        lines.buffer.push(b'\n');
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_call(json: serde_json::Value) -> ToolCall {
+        serde_json::from_value(json).unwrap()
+    }
+
+    // Answers requests with `responses` in order, one per request, and
+    // returns the raw requests it received once they've all been served.
+    fn mock_server(responses: Vec<String>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut responses = responses.into_iter();
+            let mut requests = Vec::new();
+            'connections: for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                loop {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 8192];
+                    let complete = loop {
+                        let read = stream.read(&mut buffer).unwrap_or(0);
+                        if read == 0 {
+                            break false;
                         }
-                        Err(e) => {
-                            eprintln!("\nError parsing response: {}", e);
-                            eprintln!("Problematic line: {:?}", String::from_utf8_lossy(&line));
+                        request.extend_from_slice(&buffer[..read]);
+                        let text = String::from_utf8_lossy(&request).to_lowercase();
+                        if let Some(end) = text.find("\r\n\r\n") {
+                            let length = text
+                                .lines()
+                                .find_map(|line| line.strip_prefix("content-length:"))
+                                .map_or(0, |length| length.trim().parse().unwrap());
+                            if request.len() >= end + 4 + length {
+                                break true;
+                            }
                         }
+                    };
+                    if !complete {
+                        continue 'connections;
+                    }
+                    requests.push(String::from_utf8_lossy(&request).into_owned());
+                    let Some(response) = responses.next() else {
+                        break 'connections;
+                    };
+                    stream.write_all(response.as_bytes()).unwrap();
+                    if responses.len() == 0 {
+                        break 'connections;
                     }
                 }
+            }
+            requests
+        });
+        (endpoint, handle)
+    }
 
-                Ok(results)
-            },
+    fn http_response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn tool_call_arguments_as_object_or_string() {
+        let object = tool_call(json!({
+            "function": { "name": "get_weather", "arguments": { "city": "NYC" } }
+        }));
+        let string = tool_call(json!({
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"NYC\"}" }
+        }));
+        let not_json = tool_call(json!({
+            "function": { "name": "get_weather", "arguments": "NYC" }
+        }));
+
+        assert_eq!(object.function.arguments, json!({ "city": "NYC" }));
+        assert_eq!(string.function.arguments, json!({ "city": "NYC" }));
+        assert_eq!(not_json.function.arguments, json!("NYC"));
+    }
+
+    #[test]
+    fn merge_tool_calls_across_chunks() {
+        let mut calls = Vec::new();
+        merge_tool_calls(
+            &mut calls,
+            vec![tool_call(json!({
+                "function": { "index": 0, "name": "get_weather", "arguments": "{\"city\":" }
+            }))],
+        );
+        merge_tool_calls(
+            &mut calls,
+            vec![
+                tool_call(json!({ "function": { "index": 0, "arguments": "\"NYC\"}" } })),
+                tool_call(json!({
+                    "function": { "index": 1, "name": "get_time", "arguments": { "zone": "UTC" } }
+                })),
+            ],
+        );
+        merge_tool_calls(
+            &mut calls,
+            vec![tool_call(
+                json!({ "function": { "arguments": { "format": "24h" } } }),
+            )],
         );
 
-        let flattened_stream = stream
-            .map(
-                |result: Result<Vec<Result<ChatStreamItem, String>>, Box<dyn Error>>| match result {
-                    Ok(items) => futures_util::stream::iter(items),
-                    Err(e) => futures_util::stream::iter(vec![Err(e.to_string())]),
-                },
-            )
-            .flatten();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, json!("{\"city\":\"NYC\"}"));
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(
+            calls[1].function.arguments,
+            json!({ "zone": "UTC", "format": "24h" })
+        );
+    }
+
+    #[test]
+    fn merge_tool_calls_keeps_json_looking_fragments() {
+        let mut calls = Vec::new();
+        for (name, fragment) in [
+            ("set_volume", "{\"level\":"),
+            ("", "5"),
+            ("", ",\"muted\":"),
+            ("", "true"),
+            ("", ",\"extra\":"),
+            ("", "{}"),
+            ("", "}"),
+        ] {
+            merge_tool_calls(
+                &mut calls,
+                vec![tool_call(json!({
+                    "function": { "index": 0, "name": name, "arguments": fragment }
+                }))],
+            );
+        }
 
-        Ok(Box::pin(flattened_stream))
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            calls[0].function.arguments,
+            json!("{\"level\":5,\"muted\":true,\"extra\":{}}")
+        );
+        assert_eq!(
+            parse_string_arguments(calls[0].function.arguments.clone()),
+            json!({ "level": 5, "muted": true, "extra": {} })
+        );
     }
 
-    pub fn handle_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
-        let mut tool_responses = Vec::new();
-        for tool_call in tool_calls {
-            if let Some(tool) = self
-                .tools
+    #[tokio::test]
+    async fn collect_chat_stream_merges_tool_calls() {
+        #[derive(Deserialize)]
+        struct Weather {
+            city: String,
+        }
+
+        let chunks = [
+            json!({ "message": { "role": "assistant", "content": "", "tool_calls": [
+                { "function": { "index": 0, "name": "get_weather", "arguments": "{\"city\":" } }
+            ] }, "done": false }),
+            json!({ "message": { "role": "assistant", "content": "", "tool_calls": [
+                { "function": { "index": 0, "arguments": "\"NYC\"}" } }
+            ] }, "done": false }),
+            json!({ "message": { "role": "assistant", "content": "" }, "done": true }),
+        ];
+        let items = chunks.map(|chunk| {
+            let response: ChatResponse = serde_json::from_value(chunk).unwrap();
+            Ok(ChatStreamItem {
+                tool_calls: response.message.tool_calls,
+                done: response.done,
+                ..Default::default()
+            })
+        });
+        let stream: ResponseStream<ChatStreamItem> = Box::pin(futures_util::stream::iter(items));
+
+        let reply = collect_chat_stream(stream, |_| Ok(())).await.unwrap();
+        let calls = reply.message.tool_calls.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.arguments, json!({ "city": "NYC" }));
+        assert_eq!(calls[0].parse_args::<Weather>().unwrap().city, "NYC");
+    }
+
+    #[test]
+    fn normalize_host_forms() {
+        assert_eq!(
+            normalize_host("example.com:8080"),
+            "http://example.com:8080"
+        );
+        assert_eq!(normalize_host("example.com"), "http://example.com:11434");
+        assert_eq!(normalize_host(":1234"), "http://localhost:1234");
+        assert_eq!(normalize_host("[::1]"), "http://[::1]:11434");
+        assert_eq!(normalize_host("[::1]:5000"), "http://[::1]:5000");
+        assert_eq!(normalize_host("http://example.com"), "http://example.com");
+        assert_eq!(
+            normalize_host("http://example.com/ollama/"),
+            "http://example.com/ollama"
+        );
+        assert_eq!(normalize_host("https://h.io:8443"), "https://h.io:8443");
+    }
+
+    #[test]
+    fn version_from_server_payload() {
+        let response: VersionResponse = serde_json::from_str(r#"{"version":"0.5.1"}"#).unwrap();
+        let version = Version::parse(&response.version).unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (0, 5, 1));
+        assert_eq!(version.raw, "0.5.1");
+        assert!(Version::parse("not a version").is_none());
+    }
+
+    #[test]
+    fn default_tag_normalization() {
+        assert_eq!(with_default_tag("llama3"), "llama3:latest");
+        assert_eq!(with_default_tag("llama3:latest"), "llama3:latest");
+        assert_eq!(with_default_tag("llama3:8b"), "llama3:8b");
+        assert_eq!(
+            with_default_tag("registry:5000/ns/model"),
+            "registry:5000/ns/model:latest"
+        );
+        assert_eq!(
+            with_default_tag("registry:5000/ns/model:v2"),
+            "registry:5000/ns/model:v2"
+        );
+    }
+
+    #[test]
+    fn api_url_ignores_trailing_slash() {
+        assert_eq!(
+            api_url("http://localhost:11434/", "/api/tags"),
+            api_url("http://localhost:11434", "/api/tags")
+        );
+        assert_eq!(
+            api_url("http://localhost:11434//", "/api/tags"),
+            "http://localhost:11434/api/tags"
+        );
+    }
+
+    #[test]
+    fn api_url_keeps_path_prefix() {
+        assert_eq!(
+            api_url("https://example.com/ollama", "/api/chat"),
+            "https://example.com/ollama/api/chat"
+        );
+        assert_eq!(
+            api_url("https://example.com/ollama/", "/api/chat"),
+            "https://example.com/ollama/api/chat"
+        );
+        let client = OllamaClient::new("https://example.com/ollama/".to_string(), String::new());
+        assert_eq!(
+            api_url(&client.endpoint, "/api/tags"),
+            "https://example.com/ollama/api/tags"
+        );
+    }
+
+    #[test]
+    fn generate_body_omits_raw_unless_set() {
+        let client = OllamaClient::new(DEFAULT_ENDPOINT.to_string(), "llama3".to_string());
+        let body = client
+            .generate_request_body(GenerateRequest::new("Hi"))
+            .unwrap();
+        assert!(body.get("raw").is_none());
+
+        let body = client
+            .generate_request_body(GenerateRequest::new("Hi").raw(true))
+            .unwrap();
+        assert_eq!(body["raw"], json!(true));
+    }
+
+    #[test]
+    fn generate_body_has_prompt_and_suffix() {
+        let client = OllamaClient::new(DEFAULT_ENDPOINT.to_string(), "codellama".to_string());
+        let body = client
+            .generate_request_body(GenerateRequest::new("fn main() {").suffix("}"))
+            .unwrap();
+        assert_eq!(body["prompt"], json!("fn main() {"));
+        assert_eq!(body["suffix"], json!("}"));
+        assert_eq!(body["model"], json!("codellama"));
+    }
+
+    #[test]
+    fn running_models_payload() {
+        let response: ListRunningModelsResponse = serde_json::from_value(json!({
+            "models": [{
+                "name": "llama3:latest",
+                "model": "llama3:latest",
+                "size": 5137025024u64,
+                "digest": "365c0bd3c000a25d28ddbf732fe1c6add414de7275464c4e4d1c3b5fcb5d8ad1",
+                "details": { "family": "llama", "parameter_size": "8.0B" },
+                "expires_at": "2024-06-04T14:38:31.83753-07:00",
+                "size_vram": 5137025024u64
+            }]
+        }))
+        .unwrap();
+        let model = &response.models[0];
+        assert_eq!(model.name, "llama3:latest");
+        assert_eq!(model.size, 5137025024);
+        assert_eq!(model.size_vram, 5137025024);
+        assert_eq!(model.expires_at, "2024-06-04T14:38:31.83753-07:00");
+    }
+
+    #[test]
+    fn conversation_json_round_trip() {
+        let mut conversation = Conversation::with_system("You are helpful.");
+        conversation.push(Message::user("What's in this picture?").with_images(vec![
+            general_purpose::STANDARD.encode([0x89, b'P', b'N', b'G']),
+        ]));
+        conversation.push(Message {
+            tool_calls: Some(vec![tool_call(json!({
+                "id": "call_1",
+                "function": { "name": "get_weather", "arguments": { "city": "NYC" } }
+            }))]),
+            ..Message::assistant("")
+        });
+        conversation.push(Message::tool("sunny"));
+
+        let restored = Conversation::from_json(&conversation.to_json().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&restored).unwrap(),
+            serde_json::to_value(&conversation).unwrap()
+        );
+        let messages = restored.messages();
+        assert_eq!(messages[1].images.as_ref().unwrap()[0], "iVBORw==");
+        let calls = messages[2].tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(calls[0].function.arguments, json!({ "city": "NYC" }));
+    }
+
+    #[test]
+    fn parse_args_into_struct() {
+        #[derive(Deserialize)]
+        struct Weather {
+            city: String,
+            days: u32,
+        }
+
+        let call = tool_call(json!({
+            "function": { "name": "get_weather", "arguments": { "city": "NYC", "days": 3 } }
+        }));
+        let args: Weather = call.parse_args().unwrap();
+        assert_eq!(args.city, "NYC");
+        assert_eq!(args.days, 3);
+        assert!(call.parse_args::<Vec<String>>().is_err());
+    }
+
+    fn echo_tool() -> Tool {
+        Tool::new("echo", "Echoes `text`", json!({}), |args| {
+            args["text"].as_str().unwrap_or_default().to_string()
+        })
+    }
+
+    #[test]
+    fn tool_responses_carry_call_ids() {
+        let mut client = OllamaClient::default();
+        client.add_tool(echo_tool());
+        let calls = vec![
+            tool_call(json!({
+                "id": "call_a",
+                "function": { "index": 0, "name": "echo", "arguments": { "text": "one" } }
+            })),
+            tool_call(json!({
+                "id": "call_b",
+                "function": { "index": 1, "name": "echo", "arguments": { "text": "two" } }
+            })),
+            tool_call(json!({ "function": { "name": "echo", "arguments": { "text": "three" } } })),
+        ];
+
+        let responses = client.handle_tool_calls(calls);
+        let answers: Vec<(Option<&str>, &str)> = responses
+            .iter()
+            .map(|message| (message.tool_call_id.as_deref(), message.content.as_str()))
+            .collect();
+        assert_eq!(
+            answers,
+            [
+                (Some("call_a"), "one"),
+                (Some("call_b"), "two"),
+                (None, "three")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_tool_keeps_results_in_order() {
+        let mut client = OllamaClient::default();
+        client.add_tool(echo_tool());
+        let calls = vec![
+            tool_call(json!({ "function": { "name": "echo", "arguments": { "text": "one" } } })),
+            tool_call(
+                json!({ "id": "call_b", "function": { "name": "missing", "arguments": {} } }),
+            ),
+            tool_call(json!({ "function": { "name": "echo", "arguments": { "text": "three" } } })),
+        ];
+        let expected = [
+            (None, "one"),
+            (Some("call_b"), "Error: Unknown tool missing"),
+            (None, "three"),
+        ];
+
+        for responses in [
+            client.handle_tool_calls(calls.clone()),
+            client.handle_tool_calls_async(calls).await,
+        ] {
+            let answers: Vec<(Option<&str>, &str)> = responses
                 .iter()
-                .find(|t| t.name == tool_call.function.name)
-            {
-                let result = (tool.function)(tool_call.function.arguments.clone());
-                tool_responses.push(Message {
-                    role: "tool".to_string(),
-                    content: result,
-                    images: None,
-                    tool_calls: None,
-                });
+                .map(|message| (message.tool_call_id.as_deref(), message.content.as_str()))
+                .collect();
+            assert_eq!(answers, expected);
+        }
+    }
+
+    #[test]
+    fn tool_shared_between_clients() {
+        let tool = echo_tool();
+        let mut first = OllamaClient::default().with_model("llama3");
+        let mut second = OllamaClient::default().with_model("qwen2");
+        first.add_tool(tool.clone());
+        second.add_tool(tool);
+
+        let call = || {
+            vec![tool_call(json!({
+                "function": { "name": "echo", "arguments": { "text": "hi" } }
+            }))]
+        };
+        assert_eq!(first.handle_tool_calls(call())[0].content, "hi");
+        assert_eq!(second.handle_tool_calls(call())[0].content, "hi");
+    }
+
+    #[tokio::test]
+    async fn embeddings_round_trip() {
+        let (endpoint, server) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"embedding":[0.5,-1.25,3.0]}"#,
+        )]);
+        let client = OllamaClient::new(endpoint, "llama3".to_string());
+
+        let response = client
+            .embeddings("hello", Some("nomic-embed-text"))
+            .await
+            .unwrap();
+        assert_eq!(response.embedding, vec![0.5, -1.25, 3.0]);
+
+        let request = &server.join().unwrap()[0];
+        assert!(request.starts_with("POST /api/embeddings "));
+        assert!(request.contains(r#""model":"nomic-embed-text""#));
+        assert!(request.contains(r#""prompt":"hello""#));
+    }
+
+    #[tokio::test]
+    async fn delete_missing_model_is_an_error() {
+        let (endpoint, server) = mock_server(vec![http_response(
+            "404 Not Found",
+            r#"{"error":"model 'nope' not found"}"#,
+        )]);
+        let client = OllamaClient::new(endpoint, String::new());
+
+        match client.delete_model("nope").await {
+            Err(OllamaError::Status { code, body }) => {
+                assert_eq!(code, 404);
+                assert!(body.contains("nope"));
+            }
+            other => panic!("expected a 404 error, got {:?}", other),
+        }
+        assert!(server.join().unwrap()[0].starts_with("DELETE /api/delete "));
+    }
+
+    #[tokio::test]
+    async fn bearer_token_on_every_request() {
+        let (endpoint, server) = mock_server(vec![
+            http_response("200 OK", r#"{"models":[]}"#),
+            http_response(
+                "200 OK",
+                r#"{"license":"","modelfile":"","parameters":"","template":""}"#,
+            ),
+            ndjson_response(&[json!({
+                "message": { "role": "assistant", "content": "hi" },
+                "done": true
+            })]),
+            ndjson_response(&[json!({ "response": "hi", "done": true })]),
+            ndjson_response(&[json!({ "status": "success" })]),
+            ndjson_response(&[json!({ "status": "success" })]),
+        ]);
+        let client = OllamaClient::builder()
+            .endpoint(endpoint)
+            .model("llama3")
+            .bearer_token("secret".to_string())
+            .build();
+
+        client.list_local_models().await.unwrap();
+        client.show_model_info("llama3").await.unwrap();
+        client
+            .send_chat_request(&[Message::user("hi")])
+            .await
+            .unwrap();
+        client.generate("hi").await.unwrap();
+        client.pull_model("llama3").await.unwrap();
+        client.push_model("me/llama3").await.unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 6);
+        for request in requests {
+            let authorizations = request
+                .lines()
+                .filter(|line| line.to_lowercase().starts_with("authorization:"))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                authorizations,
+                ["authorization: Bearer secret"],
+                "{}",
+                request
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn user_agent_of_a_custom_client_is_kept() {
+        let (endpoint, server) = mock_server(vec![
+            http_response("200 OK", r#"{"models":[]}"#),
+            http_response("200 OK", r#"{"models":[]}"#),
+            http_response("200 OK", r#"{"models":[]}"#),
+        ]);
+        let custom = || Client::builder().user_agent("custom/1.0").build().unwrap();
+        let mut overridden = OllamaClient::with_client(custom(), endpoint.clone(), String::new());
+        overridden.set_user_agent("override/2.0");
+
+        for client in [
+            OllamaClient::new(endpoint.clone(), String::new()),
+            OllamaClient::with_client(custom(), endpoint.clone(), String::new()),
+            overridden,
+        ] {
+            client.list_local_models().await.unwrap();
+        }
+
+        let user_agents: Vec<String> = server
+            .join()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let user_agents = request
+                    .lines()
+                    .filter_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("user-agent: ")
+                            .map(str::to_string)
+                    })
+                    .collect::<Vec<_>>();
+                user_agents.join(", ")
+            })
+            .collect();
+        assert_eq!(
+            user_agents,
+            [
+                concat!("ollama-rust/", env!("CARGO_PKG_VERSION")),
+                "custom/1.0",
+                "override/2.0"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn typed_chat_sends_the_schema() {
+        #[derive(Deserialize)]
+        struct Weather {
+            city: String,
+        }
+        impl ToolParameters for Weather {
+            fn parameters() -> serde_json::Value {
+                json!({
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                    "required": ["city"]
+                })
             }
         }
-        tool_responses
+
+        let (endpoint, server) = mock_server(vec![ndjson_response(&[json!({
+            "message": { "role": "assistant", "content": "{\"city\":\"NYC\"}" },
+            "done": true
+        })])]);
+        let client = OllamaClient::new(endpoint, "llama3".to_string());
+        let weather: Weather = client
+            .send_chat_request_typed(&[Message::user("Where?")])
+            .await
+            .unwrap();
+        assert_eq!(weather.city, "NYC");
+
+        let request = server.join().unwrap().remove(0);
+        let body: serde_json::Value =
+            serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(body["format"], Weather::parameters());
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_stream_closes_the_connection() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 8192];
+            let _ = stream.read(&mut buffer).unwrap();
+            let line =
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                line.len(),
+                line
+            )
+            .unwrap();
+            // The response never finishes, so the only way the read ends is
+            // the client closing the connection.
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            matches!(stream.read(&mut buffer), Ok(0))
+        });
+        let client = OllamaClient::new(endpoint, "llama3".to_string());
+        let token = CancellationToken::new();
+
+        let mut stream = client
+            .send_chat_request_stream_cancellable(&[Message::user("hi")], token.clone())
+            .await
+            .unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().content, "Hel");
+        token.cancel();
+        assert!(stream.next().await.is_none());
+        // Join off the runtime thread so the client's connection task can run
+        // and close the socket.
+        let closed = tokio::task::spawn_blocking(move || server.join().unwrap())
+            .await
+            .unwrap();
+        assert!(closed, "connection was not closed");
+    }
+
+    fn ndjson_response(lines: &[serde_json::Value]) -> String {
+        let body: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/x-ndjson\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn generate_continues_from_context() {
+        let (endpoint, server) = mock_server(vec![
+            ndjson_response(&[
+                json!({ "response": "Once upon", "done": false }),
+                json!({ "response": " a time", "done": true, "context": [1, 2, 3] }),
+            ]),
+            ndjson_response(&[json!({ "response": ".", "done": true, "context": [1, 2, 3, 4] })]),
+        ]);
+        let client = OllamaClient::new(endpoint, "llama3".to_string());
+
+        let first = client.generate("Tell me a story").await.unwrap();
+        assert_eq!(first.response, "Once upon a time");
+        assert_eq!(first.context, Some(vec![1, 2, 3]));
+        let second = client.generate(first.follow_up("Go on")).await.unwrap();
+        assert_eq!(second.context, Some(vec![1, 2, 3, 4]));
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("\"context\""));
+        assert!(requests[1].contains(r#""context":[1,2,3]"#));
+    }
+
+    #[tokio::test]
+    async fn stop_sequence_ends_the_stream() {
+        let (endpoint, server) = mock_server(vec![ndjson_response(&[
+            json!({ "message": { "role": "assistant", "content": "1, 2," }, "done": false }),
+            json!({
+                "message": { "role": "assistant", "content": "" },
+                "done": true,
+                "done_reason": "stop"
+            }),
+        ])]);
+        let mut client = OllamaClient::new(endpoint, "llama3".to_string());
+        client.set_stop_sequences(vec!["3".to_string()]);
+
+        let mut stream = client
+            .send_chat_request_stream(&[Message::user("Count to five")])
+            .await
+            .unwrap();
+        let mut last = None;
+        while let Some(item) = stream.next().await {
+            last = Some(item.unwrap());
+        }
+        let last = last.unwrap();
+        assert!(last.done);
+        assert_eq!(last.done_reason.as_deref(), Some("stop"));
+
+        let request = &server.join().unwrap()[0];
+        assert!(request.contains(r#""stop":["3"]"#), "{}", request);
+    }
+
+    #[test]
+    fn ndjson_lines_parse_once_and_surface_errors() {
+        let progress = parse_ndjson_line(br#"{"status":"pulling manifest"}"#, parse_progress_line);
+        assert_eq!(progress.unwrap().status, "pulling manifest");
+
+        let error = parse_ndjson_line(br#"{"error":"model not found"}"#, parse_progress_line);
+        assert!(matches!(error, Err(OllamaError::Stream(message)) if message == "model not found"));
+
+        let no_status = parse_ndjson_line(br#"{"digest":"sha256:abc"}"#, parse_progress_line);
+        assert!(matches!(no_status, Err(OllamaError::Deserialize(_))));
+    }
+
+    #[test]
+    fn rfc3339_fractions() {
+        let time = parse_rfc3339("2024-06-04T14:38:31.837530123456-07:00").unwrap();
+        let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(since_epoch.as_secs(), 1_717_537_111);
+        assert_eq!(since_epoch.subsec_nanos(), 837_530_123);
+        assert!(parse_rfc3339("2024-06-04T14:38:31.8é5Z").is_none());
+        assert!(parse_rfc3339("2024-06-04T14:38:31.éééééééééZ").is_none());
+    }
+
+    #[test]
+    fn rfc3339_out_of_range() {
+        assert!(parse_rfc3339("9999999999999999-01-01T00:00:00Z").is_none());
+        assert!(parse_rfc3339("2024-06-04T14:38:31+9999999999999999:00").is_none());
+        let model = Model {
+            name: "llama3".to_string(),
+            modified_at: "9999999999999999-01-01T00:00:00Z".to_string(),
+            size: 0,
+            details: None,
+        };
+        assert!(model.modified_at_time().is_err());
+        assert!(parse_rfc3339("9999-12-31T23:59:59Z").is_some());
     }
 }
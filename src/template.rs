@@ -0,0 +1,855 @@
+//! Renders Ollama's Go prompt templates locally, for inspecting or tweaking
+//! the exact prompt before sending it with `raw` mode.
+//!
+//! Only the subset the model templates actually use is supported: fields of
+//! the root (`.System`, `.Prompt`, `.Response`, `.Messages`) and of each
+//! message (`.Role`, `.Content`), `$` and variables, `if`/`else if`/`else`,
+//! `range`, parentheses, and the `eq`, `ne`, `not`, `and`, `or`, `len` and
+//! `slice` functions. Anything else is reported as an error rather than
+//! rendered wrongly.
+
+use crate::{Message, OllamaError, Role};
+
+/// Renders `template` with `messages` the way the server does. Consecutive
+/// messages from the same role (other than `tool`) are first merged with a
+/// blank line between them. Templates that use `.Messages` then get every
+/// message, with `.System` set to the system messages joined by blank lines.
+/// Older templates that only know `.System`, `.Prompt` and `.Response` are
+/// rendered once per exchange, and the final turn is cut off after
+/// `.Response` so the prompt ends where the model should start writing.
+pub fn render_template(template: &str, messages: &[Message]) -> Result<String, OllamaError> {
+    let nodes = parse(&tokenize(template)?)?;
+    let (system, messages) = collate(messages);
+    let mut output = String::new();
+
+    if any_expr(&nodes, &|expr| mentions(expr, "Messages")) {
+        let root = Root {
+            system,
+            messages: &messages,
+            ..Root::default()
+        };
+        render_root(&nodes, &root, &mut output)?;
+        return Ok(output);
+    }
+
+    let mut root = Root::default();
+    for message in &messages {
+        let answered = match message.role {
+            Role::System => !root.prompt.is_empty() || !root.response.is_empty(),
+            Role::User => !root.response.is_empty(),
+            _ => false,
+        };
+        if answered {
+            render_root(&nodes, &root, &mut output)?;
+            root = Root::default();
+        }
+        match message.role {
+            Role::System => root.system = message.content.clone(),
+            Role::User => root.prompt = message.content.clone(),
+            Role::Assistant => root.response = message.content.clone(),
+            Role::Tool => {}
+        }
+    }
+    render_root(&cut_after_response(&nodes, &mut false), &root, &mut output)?;
+    Ok(output)
+}
+
+fn render_root(nodes: &[Node], root: &Root, output: &mut String) -> Result<(), OllamaError> {
+    let mut scope = Scope {
+        root,
+        variables: Vec::new(),
+    };
+    render(nodes, &Value::Root, &mut scope, output)
+}
+
+#[derive(Default)]
+struct Root<'a> {
+    system: String,
+    prompt: String,
+    response: String,
+    messages: &'a [Message],
+}
+
+// Merges consecutive messages from the same role, except tool results which
+// each answer their own call, and collects the system messages' content.
+fn collate(messages: &[Message]) -> (String, Vec<Message>) {
+    let mut system = Vec::new();
+    let mut collated: Vec<Message> = Vec::new();
+    for message in messages {
+        if message.role == Role::System {
+            system.push(message.content.as_str());
+        }
+        match collated.last_mut() {
+            Some(last) if last.role == message.role && message.role != Role::Tool => {
+                last.content.push_str("\n\n");
+                last.content.push_str(&message.content);
+            }
+            _ => collated.push(message.clone()),
+        }
+    }
+    (system.join("\n\n"), collated)
+}
+
+#[derive(Clone)]
+enum Value<'a> {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Root,
+    Messages(&'a [Message]),
+    Message(&'a Message),
+}
+
+impl Value<'_> {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Root | Value::Message(_) => true,
+            Value::Messages(messages) => !messages.is_empty(),
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Value::Bool(b) => b.to_string(),
+            Value::Int(n) => n.to_string(),
+            Value::Str(s) => s.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    }
+}
+
+struct Scope<'r, 'a> {
+    root: &'r Root<'a>,
+    variables: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> Scope<'_, 'a> {
+    fn variable(&self, name: &str) -> Result<Value<'a>, OllamaError> {
+        if name == "$" {
+            return Ok(Value::Root);
+        }
+        self.variables
+            .iter()
+            .rev()
+            .find(|(variable, _)| variable == name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| unsupported(&format!("undefined variable {}", name)))
+    }
+
+    fn field(&self, value: &Value<'a>, name: &str) -> Value<'a> {
+        match (value, name) {
+            (Value::Root, "System") => Value::Str(self.root.system.clone()),
+            (Value::Root, "Prompt") => Value::Str(self.root.prompt.clone()),
+            (Value::Root, "Response") => Value::Str(self.root.response.clone()),
+            (Value::Root, "Messages") => Value::Messages(self.root.messages),
+            (Value::Message(message), "Role") => Value::Str(role_name(message.role).to_string()),
+            (Value::Message(message), "Content") => Value::Str(message.content.clone()),
+            (Value::Message(message), "Thinking") => {
+                Value::Str(message.thinking.clone().unwrap_or_default())
+            }
+            // `.Tools`, `.ToolCalls` and the like render as empty.
+            _ => Value::Nil,
+        }
+    }
+}
+
+fn unsupported(what: &str) -> OllamaError {
+    OllamaError::InvalidInput(format!("Unsupported template syntax: {}", what))
+}
+
+enum Token {
+    Text(String),
+    Action(String),
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, OllamaError> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    let mut trim_next = false;
+
+    while let Some(start) = rest.find("{{") {
+        let mut text = &rest[..start];
+        if trim_next {
+            text = text.trim_start();
+        }
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| unsupported("unclosed {{"))?;
+        let mut action = &after[..end];
+
+        if let Some(trimmed) = action.strip_prefix('-')
+            && trimmed.starts_with(char::is_whitespace)
+        {
+            text = text.trim_end();
+            action = trimmed;
+        }
+        trim_next = false;
+        if let Some(trimmed) = action.strip_suffix('-')
+            && trimmed.ends_with(char::is_whitespace)
+        {
+            trim_next = true;
+            action = trimmed;
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text.to_string()));
+        }
+        let action = action.trim();
+        if !(action.starts_with("/*") && action.ends_with("*/")) {
+            tokens.push(Token::Action(action.to_string()));
+        }
+        rest = &after[end + 2..];
+    }
+
+    let text = if trim_next { rest.trim_start() } else { rest };
+    if !text.is_empty() {
+        tokens.push(Token::Text(text.to_string()));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone)]
+enum Node {
+    Text(String),
+    Print(Expr),
+    Assign {
+        variable: String,
+        declare: bool,
+        expr: Expr,
+    },
+    If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+    Range {
+        index: Option<String>,
+        element: Option<String>,
+        list: Expr,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Clone)]
+enum Expr {
+    Literal(Value<'static>),
+    Field(Option<String>, Vec<String>),
+    Call(String, Vec<Expr>),
+}
+
+fn parse(tokens: &[Token]) -> Result<Vec<Node>, OllamaError> {
+    let mut position = 0;
+    let (nodes, terminator) = parse_block(tokens, &mut position)?;
+    match terminator {
+        None => Ok(nodes),
+        Some(action) => Err(unsupported(&format!("unexpected {{{{ {} }}}}", action))),
+    }
+}
+
+// Parses until `end`, `else ...` or the end of input, returning the
+// terminating action so the caller can tell which one it was.
+fn parse_block(
+    tokens: &[Token],
+    position: &mut usize,
+) -> Result<(Vec<Node>, Option<String>), OllamaError> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.get(*position) {
+        *position += 1;
+        let action = match token {
+            Token::Text(text) => {
+                nodes.push(Node::Text(text.clone()));
+                continue;
+            }
+            Token::Action(action) => action.as_str(),
+        };
+
+        if action == "end" || action == "else" || action.starts_with("else ") {
+            return Ok((nodes, Some(action.to_string())));
+        } else if let Some(condition) = action.strip_prefix("if ") {
+            nodes.push(parse_if(condition, tokens, position)?);
+        } else if let Some(header) = action.strip_prefix("range ") {
+            nodes.push(parse_range(header, tokens, position)?);
+        } else if let Some((variable, declare, expr)) = split_assignment(action) {
+            nodes.push(Node::Assign {
+                variable: variable.to_string(),
+                declare,
+                expr: parse_expr(expr)?,
+            });
+        } else {
+            nodes.push(Node::Print(parse_expr(action)?));
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse_if(condition: &str, tokens: &[Token], position: &mut usize) -> Result<Node, OllamaError> {
+    let mut branches = Vec::new();
+    let mut condition = parse_expr(condition)?;
+
+    loop {
+        let (body, terminator) = parse_block(tokens, position)?;
+        branches.push((condition, body));
+        match terminator.as_deref() {
+            Some("end") => return Ok(Node::If(branches, Vec::new())),
+            Some("else") => {
+                let (otherwise, terminator) = parse_block(tokens, position)?;
+                if terminator.as_deref() != Some("end") {
+                    return Err(unsupported("if without end"));
+                }
+                return Ok(Node::If(branches, otherwise));
+            }
+            Some(action) if action.starts_with("else if ") => {
+                condition = parse_expr(&action["else if ".len()..])?;
+            }
+            _ => return Err(unsupported("if without end")),
+        }
+    }
+}
+
+fn parse_range(header: &str, tokens: &[Token], position: &mut usize) -> Result<Node, OllamaError> {
+    let (index, element, list) = match header.split_once(":=") {
+        Some((variables, list)) => {
+            let variables: Vec<&str> = variables.split(',').map(str::trim).collect();
+            match variables.as_slice() {
+                [element] => (None, Some(element.to_string()), list),
+                [index, element] => (Some(index.to_string()), Some(element.to_string()), list),
+                _ => return Err(unsupported(&format!("range {}", header))),
+            }
+        }
+        None => (None, None, header),
+    };
+    let list = parse_expr(list)?;
+
+    let (body, terminator) = parse_block(tokens, position)?;
+    let otherwise = match terminator.as_deref() {
+        Some("end") => Vec::new(),
+        Some("else") => {
+            let (otherwise, terminator) = parse_block(tokens, position)?;
+            if terminator.as_deref() != Some("end") {
+                return Err(unsupported("range without end"));
+            }
+            otherwise
+        }
+        _ => return Err(unsupported("range without end")),
+    };
+    Ok(Node::Range {
+        index,
+        element,
+        list,
+        body,
+        otherwise,
+    })
+}
+
+// Splits `$x := expr` (a declaration) or `$x = expr` (an assignment).
+fn split_assignment(action: &str) -> Option<(&str, bool, &str)> {
+    if !action.starts_with('$') {
+        return None;
+    }
+    let (variable, declare, expr) = match action.split_once(":=") {
+        Some((variable, expr)) => (variable, true, expr),
+        None => {
+            let (variable, expr) = action.split_once(" = ")?;
+            (variable, false, expr)
+        }
+    };
+    let variable = variable.trim();
+    (!variable.contains(char::is_whitespace)).then_some((variable, declare, expr))
+}
+
+fn parse_expr(source: &str) -> Result<Expr, OllamaError> {
+    let words = split_words(source)?;
+    let mut position = 0;
+    let expr = parse_command(&words, &mut position)?;
+    if position != words.len() {
+        return Err(unsupported(source));
+    }
+    Ok(expr)
+}
+
+fn split_words(source: &str) -> Result<Vec<String>, OllamaError> {
+    let mut words = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            words.push(c.to_string());
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::from("\"");
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => word.push('\n'),
+                        Some('t') => word.push('\t'),
+                        Some(other) => word.push(other),
+                        None => return Err(unsupported(source)),
+                    },
+                    Some(other) => word.push(other),
+                    None => return Err(unsupported(source)),
+                }
+            }
+            words.push(word);
+        } else if c == '|' {
+            return Err(unsupported("pipelines"));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+fn parse_command(words: &[String], position: &mut usize) -> Result<Expr, OllamaError> {
+    let first = words
+        .get(*position)
+        .ok_or_else(|| unsupported("empty action"))?;
+    let is_function = first
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic())
+        && first != "true"
+        && first != "false";
+    if !is_function {
+        return parse_term(words, position);
+    }
+
+    *position += 1;
+    let mut arguments = Vec::new();
+    while words.get(*position).is_some_and(|word| word != ")") {
+        arguments.push(parse_term(words, position)?);
+    }
+    Ok(Expr::Call(first.clone(), arguments))
+}
+
+fn parse_term(words: &[String], position: &mut usize) -> Result<Expr, OllamaError> {
+    let word = words
+        .get(*position)
+        .ok_or_else(|| unsupported("missing argument"))?;
+    *position += 1;
+
+    if word == "(" {
+        let expr = parse_command(words, position)?;
+        if words.get(*position).map(String::as_str) != Some(")") {
+            return Err(unsupported("unbalanced parentheses"));
+        }
+        *position += 1;
+        return Ok(expr);
+    }
+    if let Some(literal) = word.strip_prefix('"') {
+        return Ok(Expr::Literal(Value::Str(literal.to_string())));
+    }
+    if let Ok(n) = word.parse::<i64>() {
+        return Ok(Expr::Literal(Value::Int(n)));
+    }
+    match word.as_str() {
+        "true" => return Ok(Expr::Literal(Value::Bool(true))),
+        "false" => return Ok(Expr::Literal(Value::Bool(false))),
+        "." => return Ok(Expr::Field(None, Vec::new())),
+        _ => {}
+    }
+
+    let (base, fields) = if word.starts_with('$') {
+        let mut parts = word.split('.');
+        let variable = parts.next().unwrap_or_default().to_string();
+        (Some(variable), parts)
+    } else if let Some(fields) = word.strip_prefix('.') {
+        (None, fields.split('.'))
+    } else {
+        return Err(unsupported(word));
+    };
+    Ok(Expr::Field(base, fields.map(str::to_string).collect()))
+}
+
+// Whether any expression in `nodes`, including conditions and range lists,
+// satisfies `f`.
+fn any_expr(nodes: &[Node], f: &impl Fn(&Expr) -> bool) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Print(expr) | Node::Assign { expr, .. } => f(expr),
+        Node::If(branches, otherwise) => {
+            branches
+                .iter()
+                .any(|(condition, body)| f(condition) || any_expr(body, f))
+                || any_expr(otherwise, f)
+        }
+        Node::Range {
+            list,
+            body,
+            otherwise,
+            ..
+        } => f(list) || any_expr(body, f) || any_expr(otherwise, f),
+    })
+}
+
+fn mentions(expr: &Expr, field: &str) -> bool {
+    match expr {
+        Expr::Literal(_) => false,
+        Expr::Field(_, fields) => fields.iter().any(|f| f == field),
+        Expr::Call(_, arguments) => arguments.iter().any(|argument| mentions(argument, field)),
+    }
+}
+
+// Drops every node after the first action that uses `.Response`, in template
+// order, as the server does for the turn the model is about to answer.
+fn cut_after_response(nodes: &[Node], cut: &mut bool) -> Vec<Node> {
+    let mut kept = Vec::new();
+    for node in nodes {
+        if *cut {
+            break;
+        }
+        let node = match node {
+            Node::Text(_) => node.clone(),
+            Node::Print(expr) | Node::Assign { expr, .. } => {
+                *cut = mentions(expr, "Response");
+                node.clone()
+            }
+            Node::If(branches, otherwise) => Node::If(
+                branches
+                    .iter()
+                    .map(|(condition, body)| (condition.clone(), cut_after_response(body, cut)))
+                    .collect(),
+                cut_after_response(otherwise, cut),
+            ),
+            Node::Range {
+                index,
+                element,
+                list,
+                body,
+                otherwise,
+            } => Node::Range {
+                index: index.clone(),
+                element: element.clone(),
+                list: list.clone(),
+                body: cut_after_response(body, cut),
+                otherwise: cut_after_response(otherwise, cut),
+            },
+        };
+        kept.push(node);
+    }
+    kept
+}
+
+fn evaluate<'a>(
+    expr: &Expr,
+    dot: &Value<'a>,
+    scope: &Scope<'_, 'a>,
+) -> Result<Value<'a>, OllamaError> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Field(base, fields) => {
+            let mut value = match base {
+                Some(variable) => scope.variable(variable)?,
+                None => dot.clone(),
+            };
+            for field in fields {
+                value = scope.field(&value, field);
+            }
+            Ok(value)
+        }
+        Expr::Call(function, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| evaluate(argument, dot, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            call(function, arguments)
+        }
+    }
+}
+
+fn call<'a>(function: &str, arguments: Vec<Value<'a>>) -> Result<Value<'a>, OllamaError> {
+    let value = match (function, arguments.as_slice()) {
+        ("eq", [first, rest @ ..]) if !rest.is_empty() => {
+            Value::Bool(rest.iter().any(|other| values_equal(first, other)))
+        }
+        ("ne", [a, b]) => Value::Bool(!values_equal(a, b)),
+        ("not", [a]) => Value::Bool(!a.is_truthy()),
+        ("and", [_, ..]) => arguments
+            .iter()
+            .find(|a| !a.is_truthy())
+            .unwrap_or(&arguments[arguments.len() - 1])
+            .clone(),
+        ("or", [_, ..]) => arguments
+            .iter()
+            .find(|a| a.is_truthy())
+            .unwrap_or(&arguments[arguments.len() - 1])
+            .clone(),
+        ("len", [Value::Messages(messages)]) => Value::Int(messages.len() as i64),
+        ("len", [Value::Str(s)]) => Value::Int(s.len() as i64),
+        ("slice", [Value::Messages(messages), Value::Int(start)]) => {
+            Value::Messages(messages.get(*start as usize..).unwrap_or_default())
+        }
+        (
+            "slice",
+            [
+                Value::Messages(messages),
+                Value::Int(start),
+                Value::Int(end),
+            ],
+        ) => Value::Messages(
+            messages
+                .get(*start as usize..*end as usize)
+                .unwrap_or_default(),
+        ),
+        _ => return Err(unsupported(&format!("function {}", function))),
+    };
+    Ok(value)
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Nil, Value::Nil) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn render<'a>(
+    nodes: &[Node],
+    dot: &Value<'a>,
+    scope: &mut Scope<'_, 'a>,
+    output: &mut String,
+) -> Result<(), OllamaError> {
+    // Variables live until the end of the block they were declared in.
+    let depth = scope.variables.len();
+
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Print(expr) => output.push_str(&evaluate(expr, dot, scope)?.text()),
+            Node::Assign {
+                variable,
+                declare,
+                expr,
+            } => {
+                let value = evaluate(expr, dot, scope)?;
+                let existing = scope
+                    .variables
+                    .iter_mut()
+                    .rev()
+                    .find(|(name, _)| name == variable);
+                match existing {
+                    Some((_, existing)) if !declare => *existing = value,
+                    None if !declare => {
+                        return Err(unsupported(&format!("undefined variable {}", variable)));
+                    }
+                    _ => scope.variables.push((variable.clone(), value)),
+                }
+            }
+            Node::If(branches, otherwise) => {
+                let mut taken = None;
+                for (condition, body) in branches {
+                    if evaluate(condition, dot, scope)?.is_truthy() {
+                        taken = Some(body);
+                        break;
+                    }
+                }
+                render(taken.unwrap_or(otherwise), dot, scope, output)?;
+            }
+            Node::Range {
+                index,
+                element,
+                list,
+                body,
+                otherwise,
+            } => {
+                let messages = match evaluate(list, dot, scope)? {
+                    Value::Messages(messages) => messages,
+                    Value::Nil => &[],
+                    _ => return Err(unsupported("range over something other than .Messages")),
+                };
+                if messages.is_empty() {
+                    render(otherwise, dot, scope, output)?;
+                }
+                for (i, message) in messages.iter().enumerate() {
+                    let iteration_depth = scope.variables.len();
+                    if let Some(index) = index {
+                        scope.variables.push((index.clone(), Value::Int(i as i64)));
+                    }
+                    if let Some(element) = element {
+                        scope
+                            .variables
+                            .push((element.clone(), Value::Message(message)));
+                    }
+                    render(body, &Value::Message(message), scope, output)?;
+                    scope.variables.truncate(iteration_depth);
+                }
+            }
+        }
+    }
+
+    scope.variables.truncate(depth);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The llama3.1 template from the Ollama library.
+    const LLAMA3: &str = r#"<|start_header_id|>system<|end_header_id|>
+
+Cutting Knowledge Date: December 2023
+
+{{ if .System }}{{ .System }}
+{{- end }}
+{{- if .Tools }}When you receive a tool call response, use the output to format an answer to the orginal user question.
+
+You are a helpful assistant with tool calling capabilities.
+{{- end }}<|eot_id|>
+{{- range $i, $_ := .Messages }}
+{{- $last := eq (len (slice $.Messages $i)) 1 }}
+{{- if eq .Role "user" }}<|start_header_id|>user<|end_header_id|>
+{{- if and $.Tools $last }}
+
+Given the following functions, please respond with a JSON for a function call with its proper arguments that best answers the given prompt.
+
+{{ range $.Tools }}
+{{- . }}
+{{ end }}
+{{ .Content }}<|eot_id|>
+{{- else }}
+
+{{ .Content }}<|eot_id|>
+{{- end }}{{ if $last }}<|start_header_id|>assistant<|end_header_id|>
+
+{{ end }}
+{{- else if eq .Role "assistant" }}<|start_header_id|>assistant<|end_header_id|>
+{{- if .ToolCalls }}
+{{ range .ToolCalls }}
+{"name": "{{ .Function.Name }}", "parameters": {{ .Function.Arguments }}}{{ end }}
+{{- else }}
+
+{{ .Content }}
+{{- end }}{{ if not $last }}<|eot_id|>{{ end }}
+{{- else if eq .Role "tool" }}<|start_header_id|>ipython<|end_header_id|>
+
+{{ .Content }}<|eot_id|>{{ if $last }}<|start_header_id|>assistant<|end_header_id|>
+
+{{ end }}
+{{- end }}
+{{- end }}"#;
+
+    // The mistral template from the Ollama library.
+    const MISTRAL: &str = r#"{{- if .Messages }}
+{{- range $index, $_ := .Messages }}
+{{- if eq .Role "user" }}
+{{- if and (eq (len (slice $.Messages $index)) 1) $.Tools }}[AVAILABLE_TOOLS] {{ $.Tools }}[/AVAILABLE_TOOLS]
+{{- end }}[INST] {{ if and $.System (eq (len (slice $.Messages $index)) 1) }}{{ $.System }}
+
+{{ end }}{{ .Content }}[/INST]
+{{- else if eq .Role "assistant" }}
+{{- if .Content }} {{ .Content }}
+{{- else if .ToolCalls }}[TOOL_CALLS] [
+{{- range .ToolCalls }}{"name": "{{ .Function.Name }}", "arguments": {{ .Function.Arguments }}}
+{{- end }}]
+{{- end }}</s>
+{{- else if eq .Role "tool" }}[TOOL_RESULTS] {"content": {{ .Content }}}[/TOOL_RESULTS]
+{{- end }}
+{{- end }}
+{{- else }}[INST] {{ if .System }}{{ .System }}
+
+{{ end }}{{ .Prompt }}[/INST]
+{{- end }} {{ .Response }}
+{{- if .Response }}</s>
+{{- end }}"#;
+
+    // A zephyr-style template from before `.Messages` existed.
+    const LEGACY: &str = "{{ if .System }}<|system|>\n{{ .System }}</s>\n{{ end }}\
+        {{ if .Prompt }}<|user|>\n{{ .Prompt }}</s>\n{{ end }}\
+        <|assistant|>\n{{ .Response }}</s>\n";
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::system("Be brief."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+            Message::user("Bye"),
+        ]
+    }
+
+    #[test]
+    fn llama3_template() {
+        assert_eq!(
+            render_template(LLAMA3, &conversation()).unwrap(),
+            "<|start_header_id|>system<|end_header_id|>\n\n\
+             Cutting Knowledge Date: December 2023\n\n\
+             Be brief.<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\nHello!<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nBye<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+    }
+
+    #[test]
+    fn mistral_template_collates_messages() {
+        let messages = [
+            Message::system("Be brief."),
+            Message::system("Answer in French."),
+            Message::user("Hi"),
+            Message::assistant("Bonjour"),
+            Message::user("One"),
+            Message::user("Two"),
+        ];
+        assert_eq!(
+            render_template(MISTRAL, &messages).unwrap(),
+            "[INST] Hi[/INST] Bonjour</s>\
+             [INST] Be brief.\n\nAnswer in French.\n\nOne\n\nTwo[/INST] "
+        );
+    }
+
+    #[test]
+    fn legacy_template_renders_every_turn() {
+        assert_eq!(
+            render_template(LEGACY, &conversation()).unwrap(),
+            "<|system|>\nBe brief.</s>\n\
+             <|user|>\nHi</s>\n<|assistant|>\nHello!</s>\n\
+             <|user|>\nBye</s>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn unsupported_syntax_is_an_error() {
+        for template in [
+            "{{ .Prompt | trim }}",
+            "{{ json .Messages }}",
+            "{{ with .System }}{{ . }}{{ end }}",
+            "{{ if .System }}{{ .System }}",
+            "{{ .System ",
+        ] {
+            let result = render_template(template, &conversation());
+            assert!(
+                matches!(&result, Err(OllamaError::InvalidInput(message))
+                    if message.starts_with("Unsupported template syntax")),
+                "{}: {:?}",
+                template,
+                result
+            );
+        }
+    }
+}